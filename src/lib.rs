@@ -0,0 +1,7 @@
+// Compiler of .mcf files into datapack functions
+//
+// mcfrs::pipeline::Pipeline runs every pass at once, while passes of mcfrs::compiler
+// can be chained one by one with PassExt::pass. vanilla models the datapack itself.
+
+pub mod mcfrs;
+pub mod vanilla;
@@ -1,26 +1,299 @@
-use std::error::Error;
+use std::{cell::RefCell, error::Error, path::{Path, PathBuf}, rc::Rc};
 
-use mcfrs::compiler::{back_compiler::BackCompilerExt, comment_remover::CommentRemoverExt, file_compiler::FileCompiler, macro_compiler::MacroCompilerExt, scopes_compiler::ScopesCompilerExt, substitutions_compiler::SubstitutionsCompilerExt};
-use vanilla::{datapack::Datapack, namespace::Namespace};
+use mcfrs1::{mcfrs::{call_graph::CallGraph, compiler::scope_burner::ScopeBurnerExt, config::{Config, CONFIG_FILE}, diagnostic::{Diagnostic, Severity}, docs::Documentation, formatter::{FormattedFile, Formatter}, hashed_name_map::HashedNameMap, language_server::LanguageServer, lint::{parse_level, LintRule, Linter}, pipeline::Pipeline, scaffold::Project, scope::Scope, source_map::SourceMap, util::HashedNames}, vanilla::{function_tag::FunctionTag, namespace::Namespace}};
 
-mod mcfrs;
-mod vanilla;
+struct Options {
+    // Read from mcfrs.toml, with flags written over it
+    config: Config,
+    lint_levels: Vec<(LintRule, Option<Severity>)>,
+    // fmt only reports files, that are not formatted
+    check: bool,
+}
+
+impl Options {
+    // Takes --flags out of arguments
+    fn parse(arguments: &mut Vec<&str>) -> Result<Self, String> {
+        let config_path = arguments
+            .iter()
+            .rev()
+            .find_map(|argument| argument.strip_prefix("--config="))
+            .unwrap_or(CONFIG_FILE);
+
+        let config = Config::read(Path::new(config_path))?;
+        let lint_levels = config.get_lint_levels()?;
+        let mut options = Self { config, lint_levels, check: false };
+        let mut namespaces = Vec::new();
+
+        // -D name=value, or -Dname=value
+        let mut i = 0;
+        while i < arguments.len() {
+            let definition = match arguments[i] {
+                "-D" if i + 1 < arguments.len() => arguments.drain(i..i + 2).nth(1).unwrap(),
+                "-D" => return Err(String::from("Expected -D <name>=<value>")),
+                argument if argument.starts_with("-D") => &arguments.remove(i)[2..],
+                _ => {
+                    i += 1;
+                    continue;
+                },
+            };
+
+            let (name, value) = definition
+                .split_once('=')
+                .ok_or_else(|| format!("Expected -D <name>=<value>, found \"{}\"", definition))?;
+            // Written over constant of config, even if it is named with "$" there
+            let name = name.trim_start_matches('$');
+            options.config.constants.remove(&format!("${}", name));
+            options.config.constants.insert(name.to_string(), toml::Value::String(value.to_string()));
+        }
+
+        for flag in arguments.iter().filter(|argument| argument.starts_with("--")) {
+            match *flag {
+                "--keep-comments" => options.config.build.keep_comments = true,
+                "--source-headers" => options.config.build.source_headers = true,
+                "--check" => options.check = true,
+                "--debug-names" => options.config.build.debug_names = true,
+                "--release" => options.config.build.profile = String::from("release"),
+                flag if flag.starts_with("--profile=") => options.config.build.profile = flag["--profile=".len()..].to_string(),
+                flag if flag.starts_with("--config=") => (),
+                flag if flag.starts_with("--namespace=") => namespaces.push(flag["--namespace=".len()..].to_string()),
+                flag if flag.starts_with("--width=") => options.config.build.width = Some(flag["--width=".len()..]
+                    .parse()
+                    .map_err(|_| format!("Expected --width=<columns>, found \"{}\"", flag))?),
+                flag if flag.starts_with("--external=") => options.config.build.externals.push(flag["--external=".len()..].to_string()),
+                flag if flag.starts_with("--export=") => options.config.build.exports.push(flag["--export=".len()..].to_string()),
+                flag if flag.starts_with("--lint=") => {
+                    let (rule, level) = flag["--lint=".len()..]
+                        .split_once('=')
+                        .ok_or_else(|| format!("Expected --lint=<rule>=<allow|warning|error>, found \"{}\"", flag))?;
+
+                    options.lint_levels.push((
+                        LintRule::parse(rule).ok_or_else(|| format!("Unknown lint rule \"{}\"", rule))?,
+                        parse_level(level).ok_or_else(|| format!("Unknown lint level \"{}\"", level))?,
+                    ));
+                },
+                flag => return Err(format!("Unknown flag \"{}\"", flag)),
+            }
+        }
+
+        // --namespace flags replace namespaces of config, instead of adding to them
+        if !namespaces.is_empty() {
+            options.config.datapack.namespaces = namespaces;
+        }
+
+        options.config.get_indentation()?;
+        options.config.get_constants()?;
+        options.config.get_hasher()?;
+        options.config.get_scope_naming()?;
+        arguments.retain(|argument| !argument.starts_with("--"));
+        Ok(options)
+    }
+}
+
+// Diagnostics found while compiling are printed to stderr and pushed into diagnostics
+fn compile<'a>(namespace: &'a Namespace<'a>, options: &Options, diagnostics: &Rc<RefCell<Vec<Diagnostic>>>, hashed_names: &HashedNames) -> Result<Box<dyn Iterator<Item = Scope<'a>> + 'a>, String> {
+    let found = Rc::clone(diagnostics);
+
+    Ok(Pipeline::new()
+        .config(&options.config)?
+        .hashed_names(Rc::clone(hashed_names))
+        .report(Box::new(move |diagnostic| {
+            eprintln!("{}", diagnostic);
+            found.borrow_mut().push(diagnostic);
+        }))
+        .compile(namespace))
+}
+
+// Compiled functions of every namespace, hashed names are checked for collisions between all of them
+fn compile_all<'a>(namespaces: &'a [Namespace<'a>], options: &Options, diagnostics: &Rc<RefCell<Vec<Diagnostic>>>, hashed_names: &HashedNames) -> Result<Vec<Scope<'a>>, String> {
+    let mut scopes = Vec::new();
+    for namespace in namespaces {
+        scopes.extend(compile(namespace, options, diagnostics, hashed_names)?);
+    }
+
+    Ok(scopes)
+}
+
+// Namespace of new project is named after its datapack
+fn create_project(root: PathBuf, name: String) -> Result<(), Box<dyn Error>> {
+    let project = Project::try_new(root, name.clone(), name)?;
+
+    for path in project.write()? {
+        println!("created {}", path.display());
+    }
+
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>>{
-    let datapack = Datapack::try_new(String::from("test"))?;
-    let namespace = Namespace::try_new(&datapack, String::from("ns"))?;
-
-    FileCompiler::new(&namespace)
-        .comment_remove()
-        .back()
-        .macros()
-        .scopes()
-        .substitutions()
-
-        .for_each(|scope| {
-            println!("{}:\n{}\n", scope.get_reference_name(), &scope.content);
-        });
-        // .burn()?;
+    let arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut arguments = arguments.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+
+    // Commands creating new project, before config of current one is read, so a broken one does not stop them
+    let command = arguments
+        .iter()
+        .filter(|argument| !argument.starts_with("--"))
+        .copied()
+        .collect::<Vec<_>>();
+
+    match command.as_slice() {
+        ["new", name] => return create_project(PathBuf::from(name), name.to_string()),
+        ["init"] => {
+            let directory = std::env::current_dir()?;
+            let name = directory
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            return create_project(PathBuf::from("."), name);
+        },
+        ["init", name] => return create_project(PathBuf::from("."), name.to_string()),
+        _ => (),
+    }
+
+    let options = Options::parse(&mut arguments)?;
+    let datapack = options.config.get_datapack()?;
+    let namespaces = options.config.get_namespaces(&datapack)?;
+    // Used by commands working with single namespace
+    let namespace = &namespaces[0];
+    let diagnostics = Rc::new(RefCell::new(Vec::new()));
+
+    match arguments.as_slice() {
+        [] => compile_all(&namespaces, &options, &diagnostics, &HashedNames::default())?
+            .iter()
+            .for_each(|scope| {
+                println!("{}:\n{}\n", scope.get_reference_name(), &scope.content);
+            }),
+
+        ["build"] => {
+            let hashed_names = HashedNames::default();
+            let mut scopes = compile_all(&namespaces, &options, &diagnostics, &hashed_names)?;
+
+            let errors = diagnostics.borrow().iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+            if errors > 0 {
+                return Err(format!("Build failed with {} error(s)", errors).into());
+            }
+
+            let hashed_name_map = HashedNameMap::new(&hashed_names);
+            if options.config.build.debug_names {
+                let display_names = namespaces
+                    .iter()
+                    .filter_map(|namespace| hashed_name_map.get_display_names_scope(namespace, &scopes))
+                    .collect::<Vec<_>>();
+
+                for scope in display_names.iter() {
+                    println!("run /function {} to show readable names of objectives", scope.get_reference_name());
+                }
+                scopes.extend(display_names);
+            }
+
+            SourceMap::new(scopes.iter()).write(&SourceMap::get_path(&datapack))?;
+            hashed_name_map.write(&HashedNameMap::get_path(&datapack))?;
+            scopes.into_iter().burn()?;
+        },
+
+        // Common mistakes in compiled functions, with severities set by --lint flags
+        ["lint"] => {
+            let scopes = compile_all(&namespaces, &options, &diagnostics, &HashedNames::default())?;
+            let linter = options.lint_levels
+                .iter()
+                .fold(Linter::new(), |linter, (rule, level)| linter.level(*rule, *level));
+
+            let found = linter.lint(&scopes);
+            found.iter().for_each(|diagnostic| println!("{}", diagnostic));
+
+            let errors = found
+                .iter()
+                .chain(diagnostics.borrow().iter())
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+
+            if errors > 0 {
+                return Err(format!("Lint failed with {} error(s)", errors).into());
+            }
+        },
+
+        // Rewrites .mcf files in canonical layout, --check only lists files that would change
+        ["fmt"] => {
+            let formatter = match options.config.build.width {
+                Some(width) => Formatter::new().width(width),
+                None => Formatter::new(),
+            }.indentation(options.config.get_indentation()?).version(datapack.version);
+
+            let mut changed = Vec::new();
+            for namespace in namespaces.iter() {
+                let (files, errors) = FormattedFile::read_all(namespace, &formatter);
+                errors.iter().for_each(|diagnostic| eprintln!("{}", diagnostic));
+                diagnostics.borrow_mut().extend(errors);
+                changed.extend(files.into_iter().filter(|file| file.is_changed));
+            }
+
+            for file in changed.iter() {
+                match options.check {
+                    true => println!("{} is not formatted", file.path.display()),
+                    false => std::fs::write(&file.path, &file.content)?,
+                }
+            }
+
+            if options.check && !changed.is_empty() {
+                return Err(format!("{} file(s) are not formatted", changed.len()).into());
+            }
+
+            let errors = diagnostics.borrow().len();
+            if errors > 0 {
+                return Err(format!("Formatting failed with {} error(s)", errors).into());
+            }
+        },
+
+        // Language server for editors, talking over stdin and stdout
+        ["lsp"] => LanguageServer::new(namespace).config(options.config.clone()).run()?,
+
+        // Reference of macros defined in namespace, from their doc comments
+        ["docs"] | ["docs", "markdown"] | ["docs", "html"] => {
+            let documentation = Documentation::new(namespace);
+
+            match arguments.get(1) {
+                Some(&"html") => print!("{}", documentation.to_html()),
+                _ => print!("{}", documentation.to_markdown()),
+            }
+        },
+
+        // Finds .mcf line, that line of compiled function comes from
+        ["lookup", function, line] => {
+            let source_map = SourceMap::read(&SourceMap::get_path(&datapack))?;
+            let function = match function.contains(':') {
+                true => function.to_string(),
+                false => format!("{}:{}", namespace.name, function),
+            };
+
+            match source_map.lookup(&function, line.parse()?) {
+                Some(origin) => println!("{}", origin),
+                None => return Err(format!("No line {} in function {}", line, function).into()),
+            }
+        },
+
+        // Value, that name made with #[value] in the last build comes from
+        ["unhash", name] => {
+            let hashed_name_map = HashedNameMap::read(&HashedNameMap::get_path(&datapack))?;
+
+            match hashed_name_map.lookup(name) {
+                Some(value) => println!("{}", value),
+                None => return Err(format!("{} is not a hashed name of the last build", name).into()),
+            }
+        },
+
+        // Call graph of compiled functions, in graphviz's dot or json format
+        ["graph"] | ["graph", "dot"] | ["graph", "json"] => {
+            let scopes = compile_all(&namespaces, &options, &diagnostics, &HashedNames::default())?;
+            let graph = CallGraph::new(&scopes, &FunctionTag::read_all(&datapack));
+
+            match arguments.get(1) {
+                Some(&"json") => println!("{}", graph.to_json()),
+                _ => print!("{}", graph.to_dot()),
+            }
+        },
+
+        _ => return Err(format!("Unknown command \"{}\"", arguments.join(" ")).into()),
+    }
 
     Ok(())
 }
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use serde::Serialize;
+
+use crate::{mcfrs::{scope::Scope, util::is_comment}, vanilla::function_tag::FunctionTag};
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    // Function written in .mcf file
+    File,
+    // Function made from indented block or macro
+    Generated,
+    Tag,
+    // Function called, but not compiled along with the namespace
+    External,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallKind {
+    Function,
+    Schedule,
+    // Tag running its values
+    Tag,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Serialize)]
+pub struct CallGraphNode {
+    pub id: String,
+    pub kind: NodeKind,
+    // .mcf file and line the function comes from
+    pub source: Option<String>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize)]
+pub struct CallGraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: CallKind,
+}
+
+// Functions line calls, "function <reference>" or "schedule function <reference>"
+pub fn get_calls(line: &str) -> Vec<(CallKind, &str)> {
+    if is_comment(line) {
+        return Vec::new();
+    }
+
+    let words = line.split_whitespace().collect::<Vec<_>>();
+
+    words
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] == "function")
+        .map(|(i, pair)| match i.checked_sub(1).map(|i| words[i]) {
+            Some("schedule") => (CallKind::Schedule, pair[1]),
+            _ => (CallKind::Function, pair[1]),
+        })
+        .collect()
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('"', "\\\""))
+}
+
+// Which functions call which, found by looking for "function <reference>" in scopes' content
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Serialize)]
+pub struct CallGraph {
+    pub nodes: Vec<CallGraphNode>,
+    pub edges: Vec<CallGraphEdge>,
+}
+
+impl CallGraph {
+    pub fn new(scopes: &[Scope], tags: &[FunctionTag]) -> Self {
+        let mut nodes = BTreeMap::new();
+        let mut edges = BTreeSet::new();
+
+        for scope in scopes {
+            let reference = scope.get_reference_name();
+            let kind = match scope.is_generated() {
+                true => NodeKind::Generated,
+                false => NodeKind::File,
+            };
+
+            for (kind, to) in scope.content.lines().flat_map(get_calls) {
+                edges.insert(CallGraphEdge { from: reference.clone(), to: to.to_string(), kind });
+            }
+
+            nodes.insert(reference.clone(), CallGraphNode { id: reference, kind, source: Some(scope.get_source_location()) });
+        }
+
+        for tag in tags {
+            let reference = format!("#{}", tag.name);
+
+            for value in tag.values.iter() {
+                edges.insert(CallGraphEdge { from: reference.clone(), to: value.id.clone(), kind: CallKind::Tag });
+            }
+
+            nodes.insert(reference.clone(), CallGraphNode { id: reference, kind: NodeKind::Tag, source: None });
+        }
+
+        for edge in edges.iter() {
+            nodes
+                .entry(edge.to.clone())
+                .or_insert_with(|| CallGraphNode { id: edge.to.clone(), kind: NodeKind::External, source: None });
+        }
+
+        Self {
+            nodes: nodes.into_values().collect(),
+            edges: edges.into_iter().collect(),
+        }
+    }
+
+    pub fn get_reachable<'b, I>(&self, roots: I) -> HashSet<String>
+    where I: Iterator<Item = &'b str> {
+        let mut callees: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in self.edges.iter() {
+            callees
+                .entry(edge.from.as_str())
+                .or_default()
+                .push(edge.to.as_str());
+        }
+
+        let mut reachable = HashSet::new();
+        let mut queue = roots
+            .map(|root| root.to_string())
+            .collect::<VecDeque<_>>();
+
+        while let Some(reference) = queue.pop_front() {
+            if reachable.contains(&reference) {
+                continue;
+            }
+
+            if let Some(callees) = callees.get(reference.as_str()) {
+                queue.extend(callees
+                    .iter()
+                    .filter(|callee| !reachable.contains(**callee))
+                    .map(|callee| callee.to_string()));
+            }
+
+            reachable.insert(reference);
+        }
+
+        reachable
+    }
+
+    pub fn to_dot(&self) -> String {
+        let nodes = self.nodes
+            .iter()
+            .map(|node| {
+                let label = match &node.source {
+                    Some(source) if node.kind == NodeKind::Generated => format!("{}\\n{}", node.id, source),
+                    _ => node.id.clone(),
+                };
+                let shape = match node.kind {
+                    NodeKind::File => "box",
+                    NodeKind::Generated => "ellipse",
+                    NodeKind::Tag => "hexagon",
+                    NodeKind::External => "plaintext",
+                };
+
+                format!("\t{} [label={}, shape={}];\n", quote(&node.id), quote(&label), shape)
+            });
+
+        let edges = self.edges
+            .iter()
+            .map(|edge| {
+                let style = match edge.kind {
+                    CallKind::Function => "",
+                    CallKind::Schedule => " [style=dashed, label=\"schedule\"]",
+                    CallKind::Tag => " [style=dotted]",
+                };
+
+                format!("\t{} -> {}{};\n", quote(&edge.from), quote(&edge.to), style)
+            });
+
+        format!("digraph {{\n{}\n{}}}\n", nodes.collect::<String>(), edges.collect::<String>())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(from: &str, to: &str) -> CallGraphEdge {
+        CallGraphEdge { from: from.to_string(), to: to.to_string(), kind: CallKind::Function }
+    }
+
+    #[test]
+    fn finds_reachable_through_cycles() {
+        let graph = CallGraph {
+            nodes: Vec::new(),
+            edges: vec![edge("ns:a", "ns:b"), edge("ns:b", "ns:c"), edge("ns:c", "ns:b"), edge("ns:d", "ns:a")],
+        };
+
+        let reachable = graph.get_reachable(Some("ns:a").into_iter());
+
+        assert_eq!(reachable, ["ns:a", "ns:b", "ns:c"].iter().map(|name| name.to_string()).collect());
+    }
+
+    #[test]
+    fn finds_calls_of_line() {
+        assert_eq!(get_calls("execute as @a run function ns:a"), vec![(CallKind::Function, "ns:a")]);
+        assert_eq!(get_calls("schedule function ns:b 1t"), vec![(CallKind::Schedule, "ns:b")]);
+        assert_eq!(get_calls("# function ns:c"), Vec::new());
+    }
+}
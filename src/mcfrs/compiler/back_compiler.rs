@@ -1,61 +1,98 @@
 use itertools::Itertools;
 
-use crate::mcfrs::scope::Scope;
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, scope::Scope, source_map::Origin, util::is_comment};
 
-pub struct BackCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    source: I,
-}
+// Joins lines that begin with "back" onto the line above
+// Kept comments are skipped, and moved above the line that they were in the middle of
+#[derive(Default)]
+pub struct BackCompiler;
+
+impl BackCompiler {
+    pub fn new() -> Self { Self }
+
+    fn compile<'a>(&self, mut scope: Scope<'a>) -> Scope<'a> {
+        let has_origins = !scope.origins.is_empty();
+        // Continuation lines are merged into line above, so their origin is dropped
+        let mut new_lines: Vec<(String, Option<Origin>)> = Vec::new();
+
+        for (line, origin) in scope.content.lines().zip(scope.origins.iter().cloned().map(Some).chain(std::iter::repeat(None))) {
+            let line = line.trim_end();
+            let head = new_lines.iter().rposition(|(line, _)| !is_comment(line));
+
+            match (line.trim().split(' ').next(), head) {
+                (Some("back"), Some(head)) => {
+                    let (mut head_line, head_origin) = new_lines.remove(head);
+                    head_line += line.split_once("back").unwrap().1;
+                    new_lines.push((head_line, head_origin));
+                },
+                _ => new_lines.push((line.to_string(), origin)),
+            }
+        }
 
-impl<'a, I> BackCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>>
-{
-    pub fn new(source: I) -> Self { Self { source } }
+        let (lines, origins): (Vec<_>, Vec<_>) = new_lines.into_iter().unzip();
+        if has_origins {
+            scope.origins = origins.into_iter().flatten().collect();
+        }
+        scope.content = lines.into_iter()
+            .join("\n");
+
+        scope
+    }
 }
 
-pub trait BackCompilerExt<'a, I>: Sized + Iterator<Item = Scope<'a>>
-where I: Iterator<Item = Scope<'a>> {
-    // Removes lines that begin with "#"
-    fn back(self) -> BackCompiler<'a, I>;
+impl<'a> Pass<'a> for BackCompiler {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.compile(scope)))
+    }
 }
 
-impl<'a, I> BackCompilerExt<'a, I> for I
-where I: Iterator<Item = Scope<'a>> {
-    fn back(self) -> BackCompiler<'a, I> {
-        BackCompiler::new(self)
+#[deprecated(note = "use .pass(BackCompiler::new()) instead")]
+pub trait BackCompilerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Joins lines that begin with "back" onto the line above
+    fn back(self) -> Scopes<'a> {
+        self.pass(BackCompiler::new())
     }
 }
 
-impl<'a, I> Iterator for BackCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    type Item = Scope<'a>;
+#[allow(deprecated)]
+impl<'a, I> BackCompilerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.source.next() {
-            Some(mut scope) => {
-                let mut lines = scope.content.lines().peekable();
-                let mut new_lines: Vec<String> = Vec::new();
-                while let Some(line) = lines.next() {
-                    let line = line.trim_end();
-                    let continuation = lines.by_ref()
-                        .peeking_take_while(|line| line.trim().split(" ").next() == Some("back"))
-                        .map(|line| line.split_once("back").unwrap().1.trim_end());
+#[cfg(test)]
+mod tests {
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
 
-                    let new_line = vec![line].into_iter()
-                        .chain(continuation)
-                        .collect::<String>();
+    use super::*;
 
-                    new_lines.push(new_line);
-                }
+    fn compile(content: &str) -> String {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
 
-                scope.content = new_lines.into_iter()
-                    .join("\n");
+        let content = Some(scope).into_iter().pass(BackCompiler::new()).next().unwrap().content;
+        content
+    }
 
-                Some(scope)
-            }
-            None => None
-        }
+    #[test]
+    fn joins_back_lines() {
+        assert_eq!(compile("execute\nback as @a\n\tback at @s\nback run say hi\nsay b"), "execute as @a at @s run say hi\nsay b");
     }
-}
 
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_extension_still_works() {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = String::from("say a\nback b");
 
+        let content = Some(scope).into_iter().back().next().unwrap().content;
+        assert_eq!(content, "say a b");
+    }
+
+    #[test]
+    fn skips_kept_comments() {
+        assert_eq!(compile("execute as @a\n# why\nback run say hi"), "# why\nexecute as @a run say hi");
+    }
+}
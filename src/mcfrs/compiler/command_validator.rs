@@ -0,0 +1,60 @@
+use crate::{mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, util::is_comment}, vanilla::commands::Grammar};
+
+// Checks every command against grammar of datapack's minecraft version
+// Scopes are passed on unchanged
+pub struct CommandValidator<'a> {
+    grammar: Option<Grammar>,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
+}
+
+impl<'a> Default for CommandValidator<'a> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> CommandValidator<'a> {
+    pub fn new() -> Self {
+        Self {
+            grammar: None,
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
+
+    // Called with every problem found, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    fn validate(&mut self, scope: &Scope<'a>) {
+        let grammar = self.grammar.get_or_insert_with(|| Grammar::new(scope.namespace.datapack.version));
+
+        for (i, line) in scope.content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || is_comment(line) {
+                continue;
+            }
+
+            if let Err(message) = grammar.validate(line) {
+                (self.report)(Diagnostic::new(Severity::Error, message, scope, i));
+            }
+        }
+    }
+}
+
+impl<'a> Pass<'a> for CommandValidator<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.inspect(move |scope| self.validate(scope)))
+    }
+}
+
+#[deprecated(note = "use .pass(CommandValidator::new()) instead")]
+pub trait CommandValidatorExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Checks every command against grammar of minecraft 1.17.4, scopes are passed on unchanged
+    fn validate_commands(self) -> Scopes<'a> {
+        self.pass(CommandValidator::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> CommandValidatorExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
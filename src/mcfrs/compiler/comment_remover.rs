@@ -1,46 +1,184 @@
-use crate::mcfrs::scope::Scope;
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, source_map::Origin, util::{split_comments, LineComments}};
 
-pub struct CommentRemover<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    source: I,
+// Removes empty lines, "#" comments and inline "//" and "/* */" comments
+pub struct CommentRemover<'a> {
+    keep_comments: bool,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
 }
 
-impl<'a, I> CommentRemover<'a, I>
-where I: Iterator<Item = Scope<'a>>
-{
-    pub fn new(source: I) -> Self { Self { source } }
+impl<'a> Default for CommentRemover<'a> {
+    fn default() -> Self { Self::new() }
 }
 
-pub trait CommentRemoverExt<'a, I>: Sized + Iterator<Item = Scope<'a>>
-where I: Iterator<Item = Scope<'a>> {
-    // Removes lines that begin with "#"
-    fn comment_remove(self) -> CommentRemover<'a, I>;
-}
-
-impl<'a, I> CommentRemoverExt<'a, I> for I
-where I: Iterator<Item = Scope<'a>> {
-    fn comment_remove(self) -> CommentRemover<'a, I> {
-        CommentRemover::new(self)
+impl<'a> CommentRemover<'a> {
+    pub fn new() -> Self {
+        Self {
+            keep_comments: false,
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
     }
-}
 
-impl<'a, I> Iterator for CommentRemover<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    type Item = Scope<'a>;
+    // Removes "// trailing" and "/* block */" comments, block comments can span many lines, see split_comments.
+    // When comments are kept, they are moved onto their own "#" lines, above the code.
+    // Also returns index of the line, that opened block comment that is never closed.
+    fn remove_inline_comments(&self, lines: Vec<(&str, Option<Origin>)>) -> (Vec<(String, Option<Origin>)>, Option<usize>) {
+        let mut new_lines = Vec::new();
+        // Indentation of line that opened block comment, if inside of one
+        let mut block_indent: Option<String> = None;
+        let mut block_start = 0;
+
+        for (i, (line, origin)) in lines.into_iter().enumerate() {
+            if block_indent.is_none() && line.trim_start().starts_with('#') {
+                new_lines.push((line.to_string(), origin));
+                continue;
+            }
+
+            let indent = line
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .collect::<String>();
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.source.next() {
-            Some(mut scope) => {
-                let new_content = scope.content.lines()
-                    .filter(|line| line.trim().chars().next().unwrap_or('#') != '#')
-                    .collect::<Vec<_>>()
-                    .join("\n");
+            // Comments of this line go above it, at indentation of the line that started them
+            let comment_indent = block_indent.clone().unwrap_or_else(|| indent.clone());
 
-                scope.content = new_content;
-                Some(scope)
+            let LineComments { code, comments, is_in_comment, opens_comment } = split_comments(line, block_indent.is_some());
+            if opens_comment {
+                block_indent = Some(indent);
+                block_start = i;
+            } else if !is_in_comment {
+                block_indent = None;
             }
-            None => None
+
+            if self.keep_comments {
+                comments
+                    .iter()
+                    // Doc comments, "///" and "//!", are kept as regular comments
+                    .map(|comment| comment.trim_start_matches(['/', '!']).trim())
+                    .filter(|comment| !comment.is_empty())
+                    .for_each(|comment| new_lines.push((format!("{}# {}", comment_indent, comment), origin.clone())));
+            }
+
+            new_lines.push((code.trim_end().to_string(), origin));
+        }
+
+        (new_lines, block_indent.map(|_| block_start))
+    }
+
+    // Keeps comments in compiled functions, only empty lines are removed
+    pub fn keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+        self
+    }
+
+    // Called with every block comment that is never closed, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    fn compile(&mut self, mut scope: Scope<'a>) -> Scope<'a> {
+        let has_origins = !scope.origins.is_empty();
+
+        let lines = scope.content.lines()
+            .zip(scope.origins.iter().cloned().map(Some).chain(std::iter::repeat(None)))
+            .collect::<Vec<_>>();
+
+        let (lines, unclosed) = self.remove_inline_comments(lines);
+        if let Some(i) = unclosed {
+            (self.report)(Diagnostic::new(Severity::Error, String::from("Unterminated /* comment"), &scope, i));
         }
+
+        let (lines, origins): (Vec<_>, Vec<_>) = lines
+            .into_iter()
+            .filter(|(line, _)| match line.trim().chars().next() {
+                Some('#') => self.keep_comments,
+                Some(_) => true,
+                None => false,
+            })
+            .unzip();
+
+        if has_origins {
+            scope.origins = origins.into_iter().flatten().collect();
+        }
+        scope.content = lines.join("\n");
+        scope
+    }
+}
+
+impl<'a> Pass<'a> for CommentRemover<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.compile(scope)))
     }
 }
 
+#[deprecated(note = "use .pass(CommentRemover::new()) instead")]
+pub trait CommentRemoverExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Removes empty lines and comments
+    fn comment_remove(self) -> Scopes<'a> {
+        self.pass(CommentRemover::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> CommentRemoverExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Content without comments, and lines of reported errors
+    fn remove(content: &str, keep_comments: bool) -> (String, Vec<usize>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&lines);
+        let remover = CommentRemover::new()
+            .keep_comments(keep_comments)
+            .report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.line)));
+
+        let content = Some(scope).into_iter().pass(remover).next().unwrap().content;
+        let lines = lines.borrow().clone();
+        (content, lines)
+    }
+
+    #[test]
+    fn removes_inline_comments() {
+        let content = "# note\nsay a // trailing\nsay b /* inline */ c\n\n/* many\n   lines */\nsay d";
+
+        assert_eq!(remove(content, false), (String::from("say a\nsay b c\nsay d"), vec![]));
+    }
+
+    #[test]
+    fn keeps_urls_quotes_and_tags() {
+        let content = "tellraw @a \"http://a.b // c\"\nfunction #minecraft:load\nsay a//b";
+
+        assert_eq!(remove(content, false), (content.to_string(), vec![]));
+    }
+
+    #[test]
+    fn moves_kept_comments_above_code() {
+        let content = "\tsay a // one\n\tsay b /* two */";
+
+        assert_eq!(remove(content, true), (String::from("\t# one\n\tsay a\n\t# two\n\tsay b"), vec![]));
+    }
+
+    #[test]
+    fn ignores_apostrophes_in_text() {
+        assert_eq!(remove("say it's fine // note\nexecute as @a run say don't /* x */ go", false).0, "say it's fine\nexecute as @a run say don't go");
+    }
+
+    #[test]
+    fn reports_unterminated_block_comment() {
+        let content = "say a\nsay b /* never\nclosed\nsay c";
+
+        assert_eq!(remove(content, false), (String::from("say a\nsay b"), vec![2]));
+    }
+}
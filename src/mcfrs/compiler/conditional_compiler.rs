@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::mcfrs::{compiler::pass::{Pass, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, source_map::retain_origins, util::get_block_comment_lines};
+
+pub const DEFAULT_PROFILE: &str = "debug";
+
+// Dotted version, like 1.17.4
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect()
+}
+
+// "#if ..." and such, None for any other line
+fn parse_directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    let (directive, condition) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+    match directive {
+        "#if" | "#elif" | "#else" | "#endif" => Some((directive, condition.trim())),
+        _ => None,
+    }
+}
+
+// Values that conditions of a file are evaluated with
+struct Symbols<'b> {
+    constants: &'b HashMap<String, String>,
+    profile: &'b str,
+    version: &'b str,
+}
+
+impl<'b> Symbols<'b> {
+    // or := and ("||" and)*, and := not ("&&" not)*, not := "!" not | term
+    // Once result is known, rest of condition is only parsed, so "defined $a && $a > 1" works
+    fn evaluate(&self, condition: &str) -> Result<bool, String> {
+        // "!defined" -> "!", "defined"
+        let tokens = condition
+            .split_whitespace()
+            .flat_map(|token| {
+                let negations = match token.starts_with("!=") {
+                    true => 0,
+                    false => token.chars().take_while(|&c| c == '!').count(),
+                };
+
+                std::iter::repeat_n("!", negations)
+                    .chain(Some(&token[negations..]).filter(|token| !token.is_empty()))
+                    .map(|token| token.to_string())
+            })
+            .collect::<Vec<_>>();
+        let mut position = 0;
+
+        let result = self.evaluate_or(&tokens, &mut position, true)?;
+        match tokens.get(position) {
+            Some(token) => Err(format!("Unexpected \"{}\" in condition", token)),
+            None => Ok(result),
+        }
+    }
+
+    fn evaluate_or(&self, tokens: &[String], position: &mut usize, is_needed: bool) -> Result<bool, String> {
+        let mut result = self.evaluate_and(tokens, position, is_needed)?;
+
+        while tokens.get(*position).map(|token| token == "||").unwrap_or(false) {
+            *position += 1;
+            result |= self.evaluate_and(tokens, position, is_needed && !result)?;
+        }
+
+        Ok(result)
+    }
+
+    fn evaluate_and(&self, tokens: &[String], position: &mut usize, is_needed: bool) -> Result<bool, String> {
+        let mut result = self.evaluate_not(tokens, position, is_needed)?;
+
+        while tokens.get(*position).map(|token| token == "&&").unwrap_or(false) {
+            *position += 1;
+            result &= self.evaluate_not(tokens, position, is_needed && result)?;
+        }
+
+        Ok(result)
+    }
+
+    fn evaluate_not(&self, tokens: &[String], position: &mut usize, is_needed: bool) -> Result<bool, String> {
+        match tokens.get(*position).map(|token| token.as_str()) {
+            Some("!") => {
+                *position += 1;
+                Ok(!self.evaluate_not(tokens, position, is_needed)?)
+            },
+            _ => self.evaluate_term(tokens, position, is_needed),
+        }
+    }
+
+    fn evaluate_term(&self, tokens: &[String], position: &mut usize, is_needed: bool) -> Result<bool, String> {
+        let token = tokens.get(*position).ok_or("Expected condition, found end of line")?;
+        *position += 1;
+
+        match token.as_str() {
+            "true" => return Ok(true),
+            "false" => return Ok(false),
+            "defined" => {
+                let name = tokens.get(*position).ok_or("Expected constant after \"defined\"")?;
+                *position += 1;
+
+                return match name.strip_prefix('$') {
+                    Some(name) => Ok(self.constants.contains_key(name)),
+                    None => Err(format!("Expected constant like $name after \"defined\", found \"{}\"", name)),
+                };
+            },
+            _ => {},
+        }
+
+        let operator = tokens.get(*position).ok_or_else(|| format!("Expected operator after \"{}\"", token))?;
+        let value = tokens.get(*position + 1).ok_or_else(|| format!("Expected value after \"{}\"", operator))?;
+        *position += 2;
+
+        if !is_needed {
+            return Ok(false);
+        }
+
+        let ordering = match token.as_str() {
+            "profile" => self.profile.cmp(value),
+            "version" => {
+                let expected = parse_version(value).ok_or_else(|| format!("Invalid version \"{}\"", value))?;
+                let mut version = parse_version(self.version).unwrap();
+                version.truncate(expected.len());
+
+                version.cmp(&expected)
+            },
+            name => {
+                let name = name
+                    .strip_prefix('$')
+                    .ok_or_else(|| format!("Expected profile, version or constant, found \"{}\"", name))?;
+                let constant = self.constants
+                    .get(name)
+                    .ok_or_else(|| format!("Constant \"${}\" is not defined, check it with \"defined ${}\" first", name, name))?;
+
+                match (constant.parse::<f64>(), value.parse::<f64>()) {
+                    (Ok(constant), Ok(value)) => constant.partial_cmp(&value).unwrap(),
+                    _ if operator == "==" || operator == "!=" => constant.as_str().cmp(value),
+                    _ => return Err(format!("Operator \"{}\" can only compare numbers", operator)),
+                }
+            },
+        };
+
+        match operator.as_str() {
+            "==" => Ok(ordering.is_eq()),
+            "!=" => Ok(ordering.is_ne()),
+            "<" => Ok(ordering.is_lt()),
+            "<=" => Ok(ordering.is_le()),
+            ">" => Ok(ordering.is_gt()),
+            ">=" => Ok(ordering.is_ge()),
+            operator => Err(format!("Unknown operator \"{}\" in condition", operator)),
+        }
+    }
+}
+
+// Block of #if, with #elif and #else branches
+struct Conditional {
+    // Lines of the block are not skipped by enclosing block
+    is_enclosing_active: bool,
+    // Some branch has been taken already
+    is_taken: bool,
+    is_active: bool,
+    has_else: bool,
+    // Line of the #if, numbered from 0
+    line: usize,
+}
+
+// Removes lines of "#if condition" blocks, that are not true, and the directives themselves
+// Directives inside of /* */ comments are not read
+pub struct ConditionalCompiler<'a> {
+    // Project wide constants, from config or command line
+    constants: HashMap<String, String>,
+    profile: String,
+    define_regex: Regex,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
+}
+
+impl<'a> Default for ConditionalCompiler<'a> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> ConditionalCompiler<'a> {
+    pub fn new() -> Self {
+        Self {
+            constants: HashMap::new(),
+            profile: String::from(DEFAULT_PROFILE),
+            define_regex: Regex::new(r"^define\s+\$(\S+)(?:\s+(.*))?$").unwrap(),
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
+
+    // Constant checked with "defined $name" or compared like "$name == value"
+    pub fn define(mut self, name: String, value: String) -> Self {
+        self.constants.insert(name, value);
+        self
+    }
+
+    // Build profile, compared like "profile == release"
+    pub fn profile(mut self, profile: String) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    // Called with malformed directive, that leaves the file as it is, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    // Which lines of content are kept, or the line of malformed directive, numbered from 0
+    fn get_kept_lines(&self, scope: &Scope) -> Result<Vec<bool>, (usize, String)> {
+        let mut constants = self.constants.clone();
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut is_kept = Vec::new();
+
+        let lines = scope.content.lines().zip(get_block_comment_lines(&scope.content));
+        for (i, (line, is_in_comment)) in lines.enumerate() {
+            let is_active = conditionals.last().map(|conditional| conditional.is_active).unwrap_or(true);
+            let symbols = Symbols {
+                constants: &constants,
+                profile: &self.profile,
+                version: scope.namespace.datapack.version.get_name(),
+            };
+
+            let directive = match is_in_comment {
+                true => None,
+                false => parse_directive(line),
+            };
+
+            match directive {
+                Some(("#if", condition)) => {
+                    let is_true = is_active && symbols.evaluate(condition).map_err(|message| (i, message))?;
+
+                    conditionals.push(Conditional {
+                        is_enclosing_active: is_active,
+                        is_taken: is_true,
+                        is_active: is_true,
+                        has_else: false,
+                        line: i,
+                    });
+                },
+                Some(("#elif", condition)) => {
+                    let conditional = conditionals.last_mut().ok_or_else(|| (i, String::from("#elif without #if")))?;
+                    if conditional.has_else {
+                        return Err((i, String::from("#elif after #else")));
+                    }
+
+                    let is_true = conditional.is_enclosing_active
+                        && !conditional.is_taken
+                        && symbols.evaluate(condition).map_err(|message| (i, message))?;
+
+                    conditional.is_taken |= is_true;
+                    conditional.is_active = is_true;
+                },
+                Some(("#else", _)) => {
+                    let conditional = conditionals.last_mut().ok_or_else(|| (i, String::from("#else without #if")))?;
+                    if conditional.has_else {
+                        return Err((i, String::from("#else after #else")));
+                    }
+
+                    conditional.has_else = true;
+                    conditional.is_active = conditional.is_enclosing_active && !conditional.is_taken;
+                    conditional.is_taken = true;
+                },
+                Some(_) => {
+                    conditionals.pop().ok_or_else(|| (i, String::from("#endif without #if")))?;
+                },
+                None => {
+                    // Defines of the file, that are not skipped, can be checked by conditions below them
+                    if let Some(capture) = self.define_regex.captures(line).filter(|_| is_active) {
+                        let value = capture.get(2).map(|value| value.as_str().trim()).unwrap_or_default();
+                        constants.insert(capture[1].to_string(), value.to_string());
+                    }
+
+                    is_kept.push(is_active);
+                    continue;
+                },
+            }
+
+            is_kept.push(false);
+        }
+
+        match conditionals.last() {
+            Some(conditional) => Err((conditional.line, String::from("#if without #endif"))),
+            None => Ok(is_kept),
+        }
+    }
+
+    fn compile(&mut self, mut scope: Scope<'a>) -> Scope<'a> {
+        let is_kept = match self.get_kept_lines(&scope) {
+            Ok(is_kept) => is_kept,
+            Err((i, message)) => {
+                (self.report)(Diagnostic::new(Severity::Error, message, &scope, i));
+                return scope;
+            },
+        };
+
+        if is_kept.iter().all(|&is_kept| is_kept) {
+            return scope;
+        }
+
+        let new_content = scope.content
+            .lines()
+            .zip(is_kept.iter())
+            .filter(|(_, &is_kept)| is_kept)
+            .map(|(line, _)| line)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        scope.origins = retain_origins(scope.origins, &is_kept);
+        scope.content = new_content;
+        scope
+    }
+}
+
+impl<'a> Pass<'a> for ConditionalCompiler<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.compile(scope)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Kept content, and lines of reported errors
+    fn compile(content: &str, compiler: ConditionalCompiler) -> (String, Vec<usize>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&lines);
+        let compiler = compiler.report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.line)));
+
+        let content = Some(scope).into_iter().pass(compiler).next().unwrap().content;
+        let lines = lines.borrow().clone();
+        (content, lines)
+    }
+
+    fn evaluate(condition: &str) -> Result<bool, String> {
+        let constants = vec![(String::from("level"), String::from("2")), (String::from("mode"), String::from("hard"))]
+            .into_iter()
+            .collect();
+        let symbols = Symbols { constants: &constants, profile: "debug", version: "1.17.1" };
+
+        symbols.evaluate(condition)
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        assert_eq!(evaluate("profile == debug"), Ok(true));
+        assert_eq!(evaluate("version >= 1.17 && version < 1.18"), Ok(true));
+        assert_eq!(evaluate("$level > 1 && $mode != easy"), Ok(true));
+        assert_eq!(evaluate("!defined $missing || $missing > 1"), Ok(true));
+        assert_eq!(evaluate("false || true && false"), Ok(false));
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        assert!(evaluate("$missing > 1").is_err());
+        assert!(evaluate("$mode < 3").is_err());
+        assert!(evaluate("profile ~ debug").is_err());
+        assert!(evaluate("profile == debug extra").is_err());
+        assert!(evaluate("").is_err());
+    }
+
+    #[test]
+    fn keeps_taken_branches() {
+        let content = "#if profile == release\nsay release\n#elif $level == 2\nsay two\n#if false\nsay never\n#endif\n#else\nsay other\n#endif\nsay always";
+        let compiler = ConditionalCompiler::new().define(String::from("level"), String::from("2"));
+
+        assert_eq!(compile(content, compiler), (String::from("say two\nsay always"), vec![]));
+    }
+
+    #[test]
+    fn reads_defines_of_file() {
+        let content = "define $a 1\n#if $a == 1\nsay one\n#endif";
+
+        assert_eq!(compile(content, ConditionalCompiler::new()).0, "define $a 1\nsay one");
+    }
+
+    #[test]
+    fn ignores_directives_in_block_comments() {
+        let content = "/* Example:\n#if profile == release\n*/\nsay a";
+
+        assert_eq!(compile(content, ConditionalCompiler::new()), (content.to_string(), vec![]));
+    }
+
+    #[test]
+    fn reports_malformed_directives() {
+        assert_eq!(compile("say a\n#endif", ConditionalCompiler::new()), (String::from("say a\n#endif"), vec![2]));
+        assert_eq!(compile("#if true\n#else\n#else\n#endif", ConditionalCompiler::new()).1, vec![3]);
+        assert_eq!(compile("say a\n#if true\nsay b", ConditionalCompiler::new()).1, vec![2]);
+        assert_eq!(compile("#if $a > 1\n#endif", ConditionalCompiler::new()).1, vec![1]);
+    }
+}
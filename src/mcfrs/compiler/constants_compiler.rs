@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use regex::{Captures, Regex};
+
+use crate::mcfrs::{compiler::{macro_compiler::parse_parameter, pass::{Pass, Scopes}}, diagnostic::{Diagnostic, Severity}, scope::Scope, source_map::retain_origins};
+
+// Names substituted by SubstitutionsCompiler, that can not be redefined
+const RESERVED: [&str; 2] = ["this", "namespace"];
+
+pub fn is_constant_name_valid(name: &str) -> bool {
+    !name.is_empty()
+        && !RESERVED.contains(&name)
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Parameters of "generate function" on the line, if it is one
+fn get_macro_parameters(line: &str) -> Option<Vec<String>> {
+    let mut words = line.trim().split(' ');
+    if words.next() != Some("generate") {
+        return None;
+    }
+
+    Some(words
+        .skip(2)
+        .filter(|word| !word.is_empty())
+        .map(|word| parse_parameter(word).0.trim_start_matches('$').to_string())
+        .collect())
+}
+
+// Removes "define $name value" lines and replaces $name with value, in the whole file
+// Parameters of macros are left to MacroCompiler, within the macro they take precedence over constants
+pub struct ConstantsCompiler<'a> {
+    // Project wide constants, from config or command line
+    constants: HashMap<String, String>,
+    define_regex: Regex,
+    constant_regex: Regex,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
+}
+
+impl<'a> Default for ConstantsCompiler<'a> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> ConstantsCompiler<'a> {
+    pub fn new() -> Self {
+        Self {
+            constants: HashMap::new(),
+            define_regex: Regex::new(r"^define\s+\$(\S+)(?:\s+(.*))?$").unwrap(),
+            constant_regex: Regex::new(r"\$([A-Za-z0-9_]+)").unwrap(),
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
+
+    // Constant defined for every file, "define" lines of a file take precedence over it
+    pub fn define(mut self, name: String, value: String) -> Self {
+        if !is_constant_name_valid(&name) {
+            panic!("Invalid constant name \"${}\"!", name);
+        }
+
+        self.constants.insert(name, value);
+        self
+    }
+
+    // Called with every invalid or repeated "define", which is left out, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    // Names of parameters of macros, that every line is part of
+    fn get_shadowed_names(content: &str) -> Vec<Vec<String>> {
+        // Indentation of "generate function" lines, that current line may be part of, with their parameters
+        let mut macros: Vec<(usize, Vec<String>)> = Vec::new();
+        let mut is_in_header = false;
+
+        content
+            .lines()
+            .map(|line| {
+                let indent = line.chars().take_while(|&c| c == '\t').count();
+                // "with" lines belong to the definition above them
+                is_in_header = is_in_header && line.trim_start().starts_with("with");
+
+                if !is_in_header && !line.trim().is_empty() {
+                    while macros.last().map(|(macro_indent, _)| indent <= *macro_indent).unwrap_or(false) {
+                        macros.pop();
+                    }
+                }
+
+                if let Some(parameters) = get_macro_parameters(line) {
+                    macros.push((indent, parameters));
+                    is_in_header = true;
+                }
+
+                macros
+                    .iter()
+                    .flat_map(|(_, parameters)| parameters.iter().cloned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn compile(&mut self, mut scope: Scope<'a>) -> Scope<'a> {
+        let mut constants = self.constants.clone();
+        // Defined in this file, to catch the same constant defined twice
+        let mut defined: HashMap<String, String> = HashMap::new();
+        let mut is_kept = Vec::new();
+
+        for (i, line) in scope.content.lines().enumerate() {
+            let location = match scope.origins.get(i) {
+                Some(origin) => format!("{}:{}", origin.file, origin.line),
+                None => format!("{}:{}", scope.get_reference_name(), i + 1),
+            };
+
+            match self.define_regex.captures(line) {
+                Some(capture) => {
+                    let name = &capture[1];
+                    let value = capture.get(2).map(|value| value.as_str().trim()).unwrap_or_default();
+                    is_kept.push(false);
+
+                    if !is_constant_name_valid(name) {
+                        (self.report)(Diagnostic::new(Severity::Error, format!("Invalid constant name \"${}\"", name), &scope, i));
+                        continue;
+                    }
+                    if let Some(previous) = defined.get(name) {
+                        (self.report)(Diagnostic::new(Severity::Error, format!("Constant \"${}\" is already defined at {}", name, previous), &scope, i));
+                        continue;
+                    }
+
+                    defined.insert(name.to_string(), location);
+                    constants.insert(name.to_string(), value.to_string());
+                },
+                None => is_kept.push(true),
+            }
+        }
+
+        if defined.is_empty() && constants.is_empty() {
+            return scope;
+        }
+
+        let new_content = scope.content
+            .lines()
+            .zip(Self::get_shadowed_names(&scope.content))
+            .zip(is_kept.iter())
+            .filter(|(_, &is_kept)| is_kept)
+            .map(|((line, shadowed), _)| self.constant_regex.replace_all(line, |capture: &Captures| {
+                match constants.get(&capture[1]).filter(|_| !shadowed.iter().any(|name| name == &capture[1])) {
+                    Some(value) => value.clone(),
+                    None => capture[0].to_string(),
+                }
+            }))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        scope.origins = retain_origins(scope.origins, &is_kept);
+        scope.content = new_content;
+        scope
+    }
+}
+
+impl<'a> Pass<'a> for ConstantsCompiler<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.compile(scope)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Substituted content, and lines of reported errors
+    fn compile(content: &str, compiler: ConstantsCompiler) -> (String, Vec<usize>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&lines);
+        let compiler = compiler.report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.line)));
+
+        let content = Some(scope).into_iter().pass(compiler).next().unwrap().content;
+        let lines = lines.borrow().clone();
+        (content, lines)
+    }
+
+    #[test]
+    fn substitutes_constants() {
+        let content = "define $distance 5\ntp @s ~ ~$distance ~ $unknown";
+        let compiler = ConstantsCompiler::new().define(String::from("unknown"), String::from("x"));
+
+        assert_eq!(compile(content, compiler), (String::from("tp @s ~ ~5 ~ x"), vec![]));
+    }
+
+    #[test]
+    fn define_of_file_takes_precedence() {
+        let content = "define $a 2\nsay $a";
+        let compiler = ConstantsCompiler::new().define(String::from("a"), String::from("1"));
+
+        assert_eq!(compile(content, compiler).0, "say 2");
+    }
+
+    #[test]
+    fn leaves_macro_parameters_alone() {
+        let content = "say $block\ngenerate function place $block $count=$block\nwith recursion\n\tsetblock ~ ~ ~ $block\n\t\tsay $block\nsay $block";
+        let compiler = ConstantsCompiler::new().define(String::from("block"), String::from("stone"));
+
+        assert_eq!(
+            compile(content, compiler).0,
+            "say stone\ngenerate function place $block $count=$block\nwith recursion\n\tsetblock ~ ~ ~ $block\n\t\tsay $block\nsay stone");
+    }
+
+    #[test]
+    fn substitutes_other_constants_in_macros() {
+        let content = "generate function place $block\n\tsetblock ~ ~$height ~ $block";
+        let compiler = ConstantsCompiler::new().define(String::from("height"), String::from("3"));
+
+        assert_eq!(compile(content, compiler).0, "generate function place $block\n\tsetblock ~ ~3 ~ $block");
+    }
+
+    #[test]
+    fn reports_invalid_and_repeated_defines() {
+        let content = "define $a 1\ndefine $a 2\ndefine $this 3\nsay $a";
+
+        assert_eq!(compile(content, ConstantsCompiler::new()), (String::from("say 1"), vec![2, 3]));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{mcfrs::{call_graph::CallGraph, compiler::pass::{Pass, PassExt, Scopes}, scope::Scope}, vanilla::function_tag::FunctionTag};
+
+// Removes generated scopes that can not be reached from files, function tags or exports
+// Needs every scope before it can return the first one
+#[derive(Default)]
+pub struct DeadScopeEliminator {
+    exports: Vec<String>,
+}
+
+impl DeadScopeEliminator {
+    pub fn new() -> Self { Self { exports: Vec::new() } }
+
+    // Keeps function even if nothing in datapack calls it, reference is like ns:name
+    pub fn export(mut self, reference: String) -> Self {
+        self.exports.push(reference);
+        self
+    }
+
+    fn eliminate<'a>(&self, scopes: Vec<Scope<'a>>) -> Vec<Scope<'a>> {
+        let tags = match scopes.first() {
+            Some(scope) => FunctionTag::read_all(scope.namespace.datapack),
+            None => return scopes,
+        };
+
+        let graph = CallGraph::new(&scopes, &tags);
+
+        // Files and tags can be called with /function, exports are declared to be called that way
+        let roots = scopes
+            .iter()
+            .filter(|scope| !scope.is_generated())
+            .map(|scope| scope.get_reference_name())
+            .chain(tags.iter().map(|tag| format!("#{}", tag.name)))
+            .chain(self.exports.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let reachable = graph.get_reachable(roots.iter().map(|root| root.as_str()));
+
+        scopes
+            .into_iter()
+            .filter(|scope| reachable.contains(&scope.get_reference_name()))
+            .collect()
+    }
+}
+
+impl<'a> Pass<'a> for DeadScopeEliminator {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(std::iter::once_with(move || self.eliminate(scopes.collect())).flatten())
+    }
+}
+
+#[deprecated(note = "use .pass(DeadScopeEliminator::new()) instead")]
+pub trait DeadScopeEliminatorExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Removes generated scopes that can not be reached from files or function tags
+    fn eliminate_dead_scopes(self) -> Scopes<'a> {
+        self.pass(DeadScopeEliminator::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> DeadScopeEliminatorExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    fn get_names(scopes: Vec<Scope>) -> Vec<String> {
+        scopes.iter().map(|scope| scope.get_reference_name()).collect()
+    }
+
+    #[test]
+    fn keeps_exported_scopes() {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+
+        let mut file = Scope::new(String::from("main"), &namespace);
+        file.content = String::from("function ns:_/00/00/00/00");
+        let called = Scope::new_unnamed(0, &namespace).with_parent(&file);
+        let exported = Scope::new_unnamed(1, &namespace).with_parent(&file);
+        let dead = Scope::new_unnamed(2, &namespace).with_parent(&file);
+        let scopes = vec![file, called, exported, dead];
+
+        let kept = scopes
+            .clone()
+            .into_iter()
+            .pass(DeadScopeEliminator::new())
+            .collect();
+        assert_eq!(get_names(kept), vec!["ns:main", "ns:_/00/00/00/00"]);
+
+        let kept = scopes
+            .into_iter()
+            .pass(DeadScopeEliminator::new().export(String::from("ns:_/00/00/00/01")))
+            .collect();
+        assert_eq!(get_names(kept), vec!["ns:main", "ns:_/00/00/00/00", "ns:_/00/00/00/01"]);
+    }
+}
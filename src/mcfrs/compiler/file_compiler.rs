@@ -1,12 +1,13 @@
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
 
 use walkdir::WalkDir;
 
-use crate::{mcfrs::scope::Scope, vanilla::namespace::Namespace};
+use crate::{mcfrs::{scope::Scope, source_map::Origin}, vanilla::namespace::Namespace};
 
 pub struct FileCompiler<'a> {
     namespace: &'a Namespace<'a>,
     files: Box<dyn Iterator<Item = PathBuf> + 'a>,
+    overlay: HashMap<String, String>,
 }
 
 impl<'a> Iterator for FileCompiler<'a> {
@@ -16,9 +17,9 @@ impl<'a> Iterator for FileCompiler<'a> {
         match self.files.next() {
             Some(path) => {
                 let functions_dir = self.namespace.get_functions_path();
-                let mut functions_dir = functions_dir.into_iter();
+                let mut functions_dir = functions_dir.iter();
 
-                let scope_path: PathBuf = path.into_iter()
+                let scope_path: PathBuf = path.iter()
                     .skip_while(|_| functions_dir.next().is_some())
                     .collect();
 
@@ -35,9 +36,20 @@ impl<'a> Iterator for FileCompiler<'a> {
 
                 let mut scope = Scope::new(scope_path, self.namespace);
 
+                if let Some(content) = self.overlay.get(&scope.get_source_file()) {
+                    scope.content = content.clone();
+                    scope.origins = (1..=scope.content.lines().count())
+                        .map(|line| Origin::new(scope.get_source_file(), line))
+                        .collect();
+                    return Some(scope);
+                }
+
                 match File::open(path) {
                     Ok(mut f) => {
                         f.read_to_string(&mut scope.content).unwrap();
+                        scope.origins = (1..=scope.content.lines().count())
+                            .map(|line| Origin::new(scope.get_source_file(), line))
+                            .collect();
                         Some(scope)
                     },
                     Err(_) => None
@@ -58,6 +70,7 @@ impl<'a> FileCompiler<'a> {
             .join("functions");
 
         let functions = WalkDir::new(functions_path)
+            .sort_by_file_name()
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file())
@@ -72,8 +85,15 @@ impl<'a> FileCompiler<'a> {
     pub fn new(namespace: &'a Namespace<'a>) -> Self {
         Self {
             namespace,
-            files: Self::get_files_iterator(namespace)
+            files: Self::get_files_iterator(namespace),
+            overlay: HashMap::new(),
         }
     }
+
+    // Content used instead of what is saved on disk, keyed by file like dir/name.mcf
+    pub fn overlay(mut self, overlay: HashMap<String, String>) -> Self {
+        self.overlay = overlay;
+        self
+    }
 }
 
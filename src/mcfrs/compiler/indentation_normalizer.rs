@@ -0,0 +1,236 @@
+use itertools::Itertools;
+
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, util::{get_block_comment_lines, is_comment_line}};
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Default)]
+pub enum Indentation {
+    Tabs,
+    // Number of spaces that make one level of indentation
+    Spaces(usize),
+    // Tabs, or spaces with width of the smallest space indentation found in a file
+    #[default]
+    Infer,
+}
+
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+enum LineKind {
+    Code,
+    // Starts with "#", "//" or "/*"
+    Comment,
+    // Inside of /* */ comment, that started on a line above
+    InComment,
+}
+
+fn get_line_kinds(content: &str) -> Vec<LineKind> {
+    content
+        .lines()
+        .zip(get_block_comment_lines(content))
+        .map(|(line, is_in_comment)| match (is_in_comment, is_comment_line(line)) {
+            (true, _) => LineKind::InComment,
+            (false, true) => LineKind::Comment,
+            (false, false) => LineKind::Code,
+        })
+        .collect()
+}
+
+// Converts indentation made of spaces into tabs, other passes only understand tabs
+// Comments do not count for indentation, they are only moved along with the code
+pub struct IndentationNormalizer<'a> {
+    indentation: Indentation,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
+}
+
+impl<'a> Default for IndentationNormalizer<'a> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> IndentationNormalizer<'a> {
+    pub fn new() -> Self {
+        Self {
+            indentation: Indentation::default(),
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
+
+    pub fn indentation(mut self, indentation: Indentation) -> Self {
+        self.indentation = indentation;
+        self
+    }
+
+    // Called with every badly indented line, which is left as it is, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    fn get_space_width(&self, scope: &Scope, kinds: &[LineKind]) -> Option<usize> {
+        match self.indentation {
+            Indentation::Tabs => None,
+            Indentation::Spaces(width) => Some(width),
+            Indentation::Infer => scope.content
+                .lines()
+                .zip(kinds.iter())
+                .filter(|(line, &kind)| kind == LineKind::Code && !line.trim().is_empty())
+                .map(|(line, _)| line.chars().take_while(|&c| c == ' ').count())
+                .filter(|&spaces| spaces > 0)
+                .min(),
+        }
+    }
+
+    fn normalize_line(line: &str, width: Option<usize>, block_indent: &mut Option<char>) -> Result<String, String> {
+        if line.trim().is_empty() {
+            return Ok(String::new());
+        }
+
+        let indent = line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect::<String>();
+        let code = &line[indent.len()..];
+
+        let indent_char = match indent.chars().dedup().at_most_one() {
+            Ok(Some(c)) => c,
+            Ok(None) => {
+                *block_indent = None;
+                return Ok(line.to_string());
+            },
+            Err(_) => return Err(String::from("Mixed tabs and spaces in indentation")),
+        };
+
+        match *block_indent {
+            Some(c) if c != indent_char => return Err(String::from("Mixed tabs and spaces within a block")),
+            _ => *block_indent = Some(indent_char),
+        }
+
+        let depth = match (indent_char, width) {
+            ('\t', _) => indent.len(),
+            (' ', Some(width)) if indent.len() % width == 0 => indent.len() / width,
+            (' ', Some(width)) => return Err(format!("Indentation of {} spaces is not a multiple of {}", indent.len(), width)),
+            (' ', None) => return Err(String::from("Spaces used for indentation, but tabs are expected")),
+            (c, _) => return Err(format!("Unexpected {:?} in indentation", c)),
+        };
+
+        Ok(format!("{}{}", "\t".repeat(depth), code))
+    }
+
+    // Comment is moved to the closest level of indentation, without checking it
+    fn normalize_comment_line(line: &str, width: Option<usize>) -> String {
+        let code = line.trim_start();
+        let indent = &line[..line.len() - code.len()];
+        let tabs = indent.chars().filter(|&c| c == '\t').count();
+        let spaces = indent.chars().filter(|&c| c == ' ').count();
+
+        match width {
+            Some(width) => format!("{}{}", "\t".repeat(tabs + (spaces + width / 2) / width), code),
+            None => line.to_string(),
+        }
+    }
+
+    fn normalize(&mut self, mut scope: Scope<'a>) -> Scope<'a> {
+        let kinds = get_line_kinds(&scope.content);
+        let width = self.get_space_width(&scope, &kinds);
+        // Indentation character used by the block, that current line is part of
+        let mut block_indent: Option<char> = None;
+        let mut errors = Vec::new();
+
+        let new_content = scope.content.lines()
+            .zip(kinds.iter())
+            .enumerate()
+            .map(|(i, (line, kind))| match kind {
+                LineKind::Code => match Self::normalize_line(line, width, &mut block_indent) {
+                    Ok(line) => line,
+                    Err(message) => {
+                        errors.push(Diagnostic::new(Severity::Error, message, &scope, i));
+                        line.to_string()
+                    },
+                },
+                LineKind::Comment => Self::normalize_comment_line(line, width),
+                LineKind::InComment => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        errors.into_iter().for_each(|diagnostic| (self.report)(diagnostic));
+
+        scope.content = new_content;
+        scope
+    }
+}
+
+impl<'a> Pass<'a> for IndentationNormalizer<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.normalize(scope)))
+    }
+}
+
+#[deprecated(note = "use .pass(IndentationNormalizer::new()) instead")]
+pub trait IndentationNormalizerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Converts indentation made of spaces into tabs
+    fn normalize_indentation(self) -> Scopes<'a> {
+        self.pass(IndentationNormalizer::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> IndentationNormalizerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Normalized content and lines of reported errors
+    fn normalize(content: &str, indentation: Indentation) -> (String, Vec<usize>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&lines);
+        let normalizer = IndentationNormalizer::new()
+            .indentation(indentation)
+            .report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.line)));
+
+        let content = Some(scope).into_iter().pass(normalizer).next().unwrap().content;
+        let lines = lines.borrow().clone();
+        (content, lines)
+    }
+
+    #[test]
+    fn infers_width_from_code_only() {
+        let content = "execute run function\n   # note\n    say a\n        say b";
+
+        assert_eq!(normalize(content, Indentation::Infer), (String::from("execute run function\n\t# note\n\tsay a\n\t\tsay b"), vec![]));
+    }
+
+    #[test]
+    fn ignores_block_comments() {
+        let content = "/* first\n   second */\nexecute run function\n  say a";
+
+        assert_eq!(normalize(content, Indentation::Infer), (String::from("/* first\n   second */\nexecute run function\n\tsay a"), vec![]));
+    }
+
+    #[test]
+    fn keeps_tabs() {
+        let content = "execute run function\n\tsay a\n\n\t\tsay b";
+
+        assert_eq!(normalize(content, Indentation::Tabs), (content.to_string(), vec![]));
+    }
+
+    #[test]
+    fn reports_bad_indentation() {
+        assert_eq!(normalize("a\n    b\n      c", Indentation::Spaces(4)).1, vec![3]);
+        assert_eq!(normalize("a\n\t b", Indentation::Infer).1, vec![2]);
+        assert_eq!(normalize("a\n\tb\n  c", Indentation::Infer).1, vec![3]);
+        assert_eq!(normalize("a\n  b", Indentation::Tabs).1, vec![2]);
+    }
+}
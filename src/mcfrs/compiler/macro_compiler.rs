@@ -1,36 +1,140 @@
-use std::{collections::{HashMap, HashSet}, iter::{Peekable, Rev, repeat}, ops::Range};
+use std::{collections::HashMap, fmt::Display, iter::Peekable};
 
 use itertools::Itertools;
-use sha2::digest::generic_array::sequence::Split;
 
-use crate::{mcfrs::{scope::Scope, util::get_indent}, vanilla::namespace::Namespace};
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, source_map::{fill_origins, Origin}, util::{get_indent, is_comment}};
+
+// How many inline expansions can be nested inside each other before compilation is aborted
+pub const DEFAULT_MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub enum MacroError {
+    // Name of macro, with line calling it
+    MissingDefinition(String, Option<Origin>),
+    // Chain of expansions, from the outermost call up to the one that repeats
+    Cycle(Vec<String>),
+    DepthExceeded(Vec<String>),
+    // Terminating condition could not be checked, with line calling the macro
+    InvalidCondition(String, Option<Origin>),
+    // Call leaves out required parameter or gives too many arguments, with line calling the macro
+    InvalidArguments(String, Option<Origin>),
+}
+
+impl MacroError {
+    // Line that error comes from, if it is known better than the line of outermost call
+    pub fn get_origin(&self) -> Option<&Origin> {
+        match self {
+            MacroError::MissingDefinition(_, origin)
+            | MacroError::InvalidCondition(_, origin)
+            | MacroError::InvalidArguments(_, origin) => origin.as_ref(),
+            MacroError::Cycle(_) | MacroError::DepthExceeded(_) => None,
+        }
+    }
+}
+
+impl Display for MacroError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MacroError::MissingDefinition(name, _) => write!(f, "Missing macro definition for \"{}\"", name),
+            MacroError::Cycle(chain) => write!(f, "Macro cycle detected: {}", chain.join(" -> ")),
+            MacroError::DepthExceeded(chain) => write!(f, "Macro expansion exceeded {} nested calls: {}", chain.len() - 1, chain.join(" -> ")),
+            MacroError::InvalidCondition(message, _) | MacroError::InvalidArguments(message, _) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MacroError {}
+
+// Operators of macro condition, ordering ones only compare numbers
+const CONDITION_OPERATORS: [&str; 7] = ["=", "==", "!=", "<", "<=", ">", ">="];
+
+// Terminating condition of recursive macro, set with "with until $a <op> $b"
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub struct MacroCondition {
+    left: String,
+    operator: String,
+    right: String,
+}
+
+impl MacroCondition {
+    // None if condition is not "<left> <operator> <right>", or operator is unknown
+    pub fn parse(condition: &str) -> Option<Self> {
+        let mut words = condition.split_whitespace();
+        let condition = Self {
+            left: words.next()?.to_string(),
+            operator: words.next()?.to_string(),
+            right: words.next()?.to_string(),
+        };
+
+        match words.next() {
+            Some(_) => None,
+            None if !CONDITION_OPERATORS.contains(&condition.operator.as_str()) => None,
+            None => Some(condition),
+        }
+    }
 
-pub struct MacroDefinition<'a> {
-    namespace: &'a Namespace<'a>,
+    // Err if ordering operator is given something else than numbers
+    pub fn is_met(&self, left: &str, right: &str) -> Result<bool, String> {
+        match (left.parse::<i64>(), right.parse::<i64>()) {
+            (Ok(left), Ok(right)) => match self.operator.as_str() {
+                "=" | "==" => Ok(left == right),
+                "!=" => Ok(left != right),
+                "<" => Ok(left < right),
+                "<=" => Ok(left <= right),
+                ">" => Ok(left > right),
+                ">=" => Ok(left >= right),
+                operator => unreachable!("Unknown operator \"{}\" in macro condition!", operator),
+            },
+            _ => match self.operator.as_str() {
+                "=" | "==" => Ok(left == right),
+                "!=" => Ok(left != right),
+                operator => Err(format!("Operator \"{}\" can only compare numbers, found \"{}\" and \"{}\"", operator, left, right)),
+            },
+        }
+    }
+}
 
+pub struct MacroDefinition {
     name: String,
     parameters: Vec<String>,
+    // Values of parameters left out by a call, set with "$name=value"
+    defaults: Vec<Option<String>>,
     body: String,
+    // Origins of body's lines
+    origins: Vec<Origin>,
 
     has_separate_scope: bool,
+    allows_recursion: bool,
+    until: Option<MacroCondition>,
 }
 
-impl<'a> MacroDefinition<'a> {
-    pub fn new<'b, I>(namespace: &'a Namespace<'a>, definition: &str, content: &mut Peekable<I>) -> Self 
-    where I: Iterator<Item = &'b str> {
+impl MacroDefinition {
+    // Content yields lines with their index, origins of content's lines are looked up by it
+    // Whole definition is taken out of content, even if it is not valid
+    pub fn new<'b, I>(definition: &str, content: &mut Peekable<I>, origins: &[Origin]) -> Result<Self, String>
+    where I: Iterator<Item = (usize, &'b str)> {
         let mut words = definition
             .trim()
-            .split(" ")
+            .split(' ')
             .skip(2);
 
         let macro_name = words
             .next()
-            .unwrap()
+            .unwrap_or_default()
             .to_string();
+        let mut error = match macro_name.is_empty() {
+            true => Some(String::from("Expected name of macro after \"generate function\"")),
+            false => None,
+        };
 
-        let macro_parameters = words
-            .map(|p| p.to_string())
-            .collect::<Vec<_>>();
+        let (macro_parameters, macro_defaults): (Vec<_>, Vec<_>) = words
+            .filter(|p| !p.is_empty())
+            .map(parse_parameter)
+            .unzip();
 
 
         let indent = definition
@@ -38,29 +142,46 @@ impl<'a> MacroDefinition<'a> {
             .unwrap();
 
         let mut has_separate_scope = false;
+        let mut allows_recursion = false;
+        let mut until = None;
 
         content
-            .peeking_take_while(|line| match line.split_once("with") {
-                Some((prefix, _)) => prefix.trim().len() == 0,
+            .peeking_take_while(|(_, line)| match line.split_once("with") {
+                Some((prefix, _)) => prefix.trim().is_empty(),
                 None => false
             })
-            .map(|line| line.split_once("with").unwrap().1.trim())
-            .for_each(|setting| {
-                if setting == "scope" { has_separate_scope = true; }
+            .map(|(_, line)| line.split_once("with").unwrap().1.trim())
+            .for_each(|setting| match setting.split_once(' ') {
+                Some(("until", condition)) => match MacroCondition::parse(condition) {
+                    Some(condition) => until = Some(condition),
+                    None => error = Some(format!("Invalid condition \"{}\" of macro \"{}\"", condition, macro_name)),
+                },
+                _ => match setting {
+                    "scope" => has_separate_scope = true,
+                    "recursion" => allows_recursion = true,
+                    _ => {},
+                },
             });
 
-        let macro_body = content
-            .peeking_take_while(|line| line.chars().skip(indent).next() == Some('\t'))
-            .map(|line| &line[indent + 1..])
-            .join("\n");
+        let (body_origins, macro_body): (Vec<_>, Vec<_>) = content
+            .peeking_take_while(|(_, line)| line.chars().nth(indent) == Some('\t'))
+            .map(|(i, line)| (origins.get(i).cloned(), &line[indent + 1..]))
+            .unzip();
 
-        Self {
-            namespace,
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        Ok(Self {
             name: macro_name,
             parameters: macro_parameters,
-            body: macro_body,
-            has_separate_scope
-        }
+            defaults: macro_defaults,
+            body: macro_body.join("\n"),
+            origins: fill_origins(body_origins),
+            has_separate_scope,
+            allows_recursion,
+            until,
+        })
     }
 
     pub fn call_into_string<'b, I>(&'b self, parameters: I) -> String
@@ -76,90 +197,299 @@ impl<'a> MacroDefinition<'a> {
 
     pub fn call_into_code<'b, I>(&'b self, parameters: I, out: &mut Vec<String>, indent: usize)
     where I: Iterator<Item = &'b str> {
+        let body = self.substitute(self.body.as_str(), parameters);
+        let indent = "\t".repeat(indent);
+
+        body
+            .lines()
+            .map(|line| format!("{}{}\n", indent, line))
+            .for_each(|line| out.push(line));
+
+    }
+
+    // Origins of body's lines, pasted by calls in expansion
+    pub fn get_origins(&self, expansion: &[String]) -> Vec<Origin> {
+        self.origins
+            .iter()
+            .map(|origin| Origin { expansion: expansion.to_vec(), ..origin.clone() })
+            .collect()
+    }
+
+    // Every parameter without default value has to be given, and nothing more
+    pub fn check_arguments(&self, count: usize) -> Result<(), String> {
+        if count > self.parameters.len() {
+            return Err(format!("Macro \"{}\" takes {} argument(s), found {}", self.name, self.parameters.len(), count));
+        }
+
+        match self.parameters.iter().zip(self.defaults.iter()).skip(count).find(|(_, default)| default.is_none()) {
+            Some((name, _)) => Err(format!("Missing argument {} of macro \"{}\"", name, self.name)),
+            None => Ok(()),
+        }
+    }
+
+    // Checks if recursion of this macro should stop for given arguments
+    pub fn is_terminated<'b, I>(&'b self, parameters: I) -> Result<bool, String>
+    where I: Iterator<Item = &'b str> + Clone {
+        match &self.until {
+            Some(condition) => condition.is_met(
+                &self.substitute(&condition.left, parameters.clone()),
+                &self.substitute(&condition.right, parameters)),
+            None => Ok(false),
+        }
+    }
+
+    fn substitute<'b, I>(&self, text: &str, parameters: I) -> String
+    where I: Iterator<Item = &'b str> {
+        let mut parameters = parameters.map(Some);
+
+        // Longest names go first, so "$n" does not eat into "$name"
         let parameters = self
             .parameters
             .iter()
-            .zip(parameters);
+            .zip(self.defaults.iter())
+            .filter_map(|(name, default)| Some((name, parameters.next().flatten().or(default.as_deref())?)))
+            .sorted_by_key(|(name, _)| std::cmp::Reverse(name.len()));
 
-        let mut body = self
-            .body
-            .clone();
+        let mut text = text.to_string();
 
         for (name, value) in parameters {
-            body = body
+            text = text
                 .replace(name, value);
         }
 
-        let indent = repeat("\t")
-            .take(indent)
-            .collect::<String>();
+        text
+    }
+}
 
-        let indent = indent.as_str();
+// Splits "$name=default" parameter of definition
+pub fn parse_parameter(parameter: &str) -> (String, Option<String>) {
+    match parameter.split_once('=') {
+        Some((name, default)) => (name.to_string(), Some(default.to_string())),
+        None => (parameter.to_string(), None),
+    }
+}
 
-        body
-            .lines()
-            .map(|line| format!("{}{}\n", indent, line))
-            .for_each(|line| out.push(line));
+// Evaluates "(a+b)" style arguments of calls, so recursive macros can count
+fn evaluate_argument(argument: &str) -> String {
+    let expression = match argument.strip_prefix('(').and_then(|a| a.strip_suffix(')')) {
+        Some(expression) => expression,
+        None => return argument.to_string(),
+    };
+
+    let operator = expression
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| "+-*/%".contains(*c));
+
+    let result = operator.and_then(|(i, operator)| {
+        let left = expression[..i].trim().parse::<i64>().ok()?;
+        let right = expression[i + 1..].trim().parse::<i64>().ok()?;
+
+        match operator {
+            '+' => left.checked_add(right),
+            '-' => left.checked_sub(right),
+            '*' => left.checked_mul(right),
+            '/' => left.checked_div(right),
+            '%' => left.checked_rem(right),
+            _ => None,
+        }
+    });
 
+    match result {
+        Some(result) => result.to_string(),
+        None => argument.to_string(),
     }
+}
 
+// Splits line into part preceeding "call" keyword and the call itself
+// "call" is a command, so it has to start the line or follow "run"
+fn split_call(line: &str) -> Option<(&str, &str)> {
+    line.match_indices("call")
+        .map(|(i, _)| (&line[..i], &line[i + 4..]))
+        .find(|(prefix, payload)| {
+            (prefix.trim().is_empty() || (prefix.ends_with(' ') && prefix.trim_end().rsplit(' ').next() == Some("run")))
+                && payload.starts_with(' ')
+        })
 }
 
-pub struct MacroCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    source: I,
+// Compiles generate function stuff
+// If definition preceeds call, call will paste definition content, not call generated function
+pub struct MacroCompiler<'a> {
     buffered: Vec<Scope<'a>>,
 
-    definitions: Vec<MacroDefinition<'a>>,
+    definitions: Vec<MacroDefinition>,
     calls: HashMap<(String, String), String>,
-    next_scope_id: Rev<Range<usize>>,
-}
-
-pub trait MacroCompilerExt<'a, I>: Sized + Iterator<Item = Scope<'a>>
-where I: Iterator<Item = Scope<'a>> {
-    // Compiles generate function stuff
-    // If definition preceeds call, call will paste definition content, not call generated function
-    fn macros(self) -> MacroCompiler<'a, I>;
+    // Expansions that scopes of "with scope" macros were generated by, by reference name
+    chains: HashMap<String, Vec<String>>,
+    max_depth: usize,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
 }
 
-impl<'a, I> MacroCompilerExt<'a, I> for I
-where I: Iterator<Item = Scope<'a>> {
-    fn macros(self) -> MacroCompiler<'a, I> {
-        MacroCompiler::new(self)
-    }
+impl<'a> Default for MacroCompiler<'a> {
+    fn default() -> Self { Self::new() }
 }
 
-impl<'a, I> MacroCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    pub fn new(source: I) -> Self {
+impl<'a> MacroCompiler<'a> {
+    pub fn new() -> Self {
         Self {
-            source,
             buffered: Vec::new(),
             definitions: Vec::new(),
             calls: HashMap::new(),
-            next_scope_id: (0 as usize..usize::MAX).rev()
+            chains: HashMap::new(),
+            max_depth: DEFAULT_MAX_EXPANSION_DEPTH,
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
         }
     }
-}
 
+    // Called with every invalid definition and every call that can not be pasted, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
 
-impl<'a, I> Iterator for MacroCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    type Item = Scope<'a>;
+    // Limits how deep macros can expand into each other
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let scope = match self.source.next() {
-            Some(scope) => Some(scope),
-            None => self.buffered.pop(),
+    // Pastes macro calls found in lines into out, expanding calls found in pasted code as well.
+    // Chain holds "name arguments" of every expansion currently in progress, scopes of "with scope" macros
+    // continue the chain that generated them.
+    fn expand_lines(&mut self, scope: &Scope<'a>, lines: Vec<(String, Option<Origin>)>, chain: &mut Vec<String>, out: &mut Vec<(String, Option<Origin>)>) -> Result<(), MacroError> {
+        for (line, origin) in lines {
+            let call = match is_comment(&line) {
+                true => None,
+                false => split_call(&line),
+            };
+
+            let (prefix, payload) = match call {
+                Some(call) => call,
+                None => {
+                    out.push((format!("{}\n", line.trim_end_matches('\n')), origin));
+                    continue;
+                }
+            };
+
+            let mut payload = payload.split_whitespace();
+            let macro_name = payload.next().unwrap();
+            let macro_parameters = payload
+                .map(evaluate_argument)
+                .collect::<Vec<_>>();
+
+            let name_param = (
+                macro_name.to_string(),
+                macro_parameters.iter().join(" ")
+            );
+
+            if let Some(name) = self.calls.get(&name_param) {
+                out.push((format!("{}function {}\n", prefix, name), origin));
+                continue;
+            }
+
+            let definition = self
+                .definitions
+                .iter()
+                .find(|definition| definition.name == macro_name)
+                .ok_or_else(|| MacroError::MissingDefinition(macro_name.to_string(), origin.clone()))?;
+            definition
+                .check_arguments(macro_parameters.len())
+                .map_err(|message| MacroError::InvalidArguments(message, origin.clone()))?;
+
+            let parameters = macro_parameters.iter().map(|p| p.as_str());
+
+            let link = format!("{} {}", name_param.0, name_param.1)
+                .trim_end()
+                .to_string();
+
+            let expansion = match &origin {
+                Some(origin) => origin.expansion
+                    .iter()
+                    .cloned()
+                    .chain(Some(format!("{} at {}:{}", link, origin.file, origin.line)))
+                    .collect(),
+                None => vec![link.clone()],
+            };
+
+            let is_terminated = definition
+                .is_terminated(parameters.clone())
+                .map_err(|message| MacroError::InvalidCondition(message, origin.clone()))?;
+            if is_terminated {
+                continue;
+            }
+
+            let is_cycle = chain.contains(&link) || (!definition.allows_recursion && chain
+                .iter()
+                .any(|call| call.split(' ').next() == Some(macro_name)));
+
+            if is_cycle || chain.len() >= self.max_depth {
+                let mut chain = chain.clone();
+                chain.push(link);
+
+                return Err(match is_cycle {
+                    true => MacroError::Cycle(chain),
+                    false => MacroError::DepthExceeded(chain),
+                });
+            }
+
+            if definition.has_separate_scope {
+                let mut new_scope = Scope::new_hashed_scope(
+                    macro_name,
+                    &format!("{} {}", name_param.0, name_param.1),
+                    scope.namespace,
+                    definition.call_into_string(parameters))
+                    .with_parent(scope);
+                new_scope.origins = definition.get_origins(&expansion);
+
+                out.push((format!("{}function {}\n", prefix, new_scope.get_reference_name()), origin));
+                self
+                    .chains
+                    .insert(new_scope.get_reference_name(), chain.iter().cloned().chain(Some(link)).collect());
+                self
+                    .calls
+                    .insert(name_param, new_scope.get_reference_name());
+                self.buffered.push(new_scope);
+                continue;
+            }
+
+            let mut body = Vec::new();
+            let indent = if prefix.trim().is_empty() {
+                prefix.len()
+            } else {
+                // Command preceeding call runs pasted code as a nested scope
+                out.push((format!("{}function\n", prefix), origin));
+                get_indent(prefix)
+            };
+            definition.call_into_code(parameters, &mut body, indent);
+
+            let body = body
+                .into_iter()
+                .zip(definition.get_origins(&expansion).into_iter().map(Some).chain(std::iter::repeat(None)))
+                .collect();
+
+            chain.push(link);
+            self.expand_lines(scope, body, chain, out)?;
+            chain.pop();
+        }
+
+        Ok(())
+    }
+
+    // Scopes, that call macros defined in later files, are compiled once every file is read
+    fn next_scope(&mut self, source: &mut Scopes<'a>) -> Option<Scope<'a>> {
+        let (scope, is_buffered) = match source.next() {
+            Some(scope) => (Some(scope), false),
+            None => (self.buffered.pop(), true),
         };
 
         match scope {
             Some(mut scope) => {
-                let content = scope
-                    .content;
+                let content = std::mem::take(&mut scope
+                    .content);
 
                 let mut content = content
                     .lines()
+                    .enumerate()
                     .peekable();
 
                 let mut new_body = Vec::new();
@@ -167,96 +497,237 @@ where I: Iterator<Item = Scope<'a>> {
                 // Separate all macro definitions from normal scope's code
                 while content.peek().is_some() {
                     let mut body = content
-                        .peeking_take_while(|line| line.trim().split(" ").next() != Some("generate"))
+                        .peeking_take_while(|(_, line)| line.trim().split(' ').next() != Some("generate"))
+                        .map(|(i, line)| (line.to_string(), scope.origins.get(i).cloned()))
                         .collect::<Vec<_>>();
 
                     new_body.append(&mut body);
 
-                    if let Some(definition) = content.next() {
-                        self
-                            .definitions
-                            .push(MacroDefinition::new(scope.namespace, definition, &mut content));
+                    if let Some((i, definition)) = content.next() {
+                        match MacroDefinition::new(definition, &mut content, &scope.origins) {
+                            Ok(definition) => self.definitions.push(definition),
+                            Err(message) => (self.report)(Diagnostic::new(Severity::Error, message, &scope, i)),
+                        }
                     }
                 }
 
-                let mut newer_body: Vec<String> = Vec::new();
-                let mut scope_was_polluted = false;
-
-                // Convert macro calls into valid commands
-                for line in new_body {
-                    match line.split_once("call") {
-                        Some((prefix, payload)) => {
-                            let mut payload = payload.trim().split(" ");
-                            let macro_name = payload.next().unwrap();
-                            let macro_parameters = payload.collect::<Vec<_>>();
-
-                            let name_param = (
-                                macro_name.to_string(),
-                                macro_parameters.iter().join(" ")
-                            );
-
-                            match self
-                                .calls
-                                .iter() 
-                                .find(|(key, _)| **key == name_param) {
-                                
-                                Some((_, name)) => {
-                                    newer_body.push(format!("{}function {}\n", prefix, name));
-                                },
-                                None => {
-                                    let definition = self
-                                        .definitions
-                                        .iter()
-                                        .find(|definition| definition.name == macro_name)
-                                        .expect(&format!("Missing macro definition for \"{}\"!", macro_name));
-
-                                    if definition.has_separate_scope {
-
-                                        let next_id = self.next_scope_id.next().unwrap();
-                                        let scope = Scope::new_unnamed_scope(
-                                            next_id,
-                                            scope.namespace,
-                                            definition.call_into_string(macro_parameters.into_iter()));
-
-                                        self
-                                            .calls
-                                            .insert(name_param, scope.get_reference_name());
-
-                                        newer_body.push(format!("{}function {}\n", prefix, scope.get_reference_name()));
-                                        self.buffered.push(scope);
-
-                                    } else {
-                                        scope_was_polluted = true;
-                                        let indent = if prefix.trim().len() == 0 {
-                                            prefix.len()
-                                        } else {
-                                            get_indent(prefix)
-                                        };
-                                        definition.call_into_code(macro_parameters.into_iter(), &mut newer_body, indent);
-                                    }
-                                }
-                            }
-
-                        },
-                        None => newer_body.push(format!("{}\n", line)),
-                    } 
+                let mut newer_body: Vec<(String, Option<Origin>)> = Vec::new();
+                let mut errors = Vec::new();
+
+                let chain = self
+                    .chains
+                    .get(&scope.get_reference_name())
+                    .cloned()
+                    .unwrap_or_default();
+
+                // Convert macro calls into valid commands, line by line so errors point at the line they come from
+                for (i, line) in new_body.iter().enumerate() {
+                    let mut expanded = Vec::new();
+
+                    match self.expand_lines(&scope, vec![line.clone()], &mut chain.clone(), &mut expanded) {
+                        Ok(()) => newer_body.append(&mut expanded),
+                        Err(error) => errors.push((i, error)),
+                    }
                 }
 
-                scope.content = newer_body
-                    .into_iter()
-                    .collect();
+                let is_missing_definition = errors
+                    .iter()
+                    .any(|(_, error)| matches!(error, MacroError::MissingDefinition(..)));
+
+                // Definition may still come from one of the remaining files
+                if is_missing_definition && !is_buffered {
+                    let (content, origins): (Vec<_>, Vec<_>) = new_body
+                        .into_iter()
+                        .unzip();
+
+                    scope.content = content.join("\n");
+                    scope.origins = fill_origins(origins);
+                    self.buffered.insert(0, scope);
+                    return self.next_scope(source);
+                }
 
-                if scope_was_polluted {
-                    self.buffered.push(scope);
-                    self.next()
-                } else {
-                    Some(scope)
+                // Lines that can not be pasted are left out
+                for (i, error) in errors {
+                    (self.report)(Diagnostic {
+                        severity: Severity::Error,
+                        message: error.to_string(),
+                        function: scope.get_reference_name(),
+                        line: i + 1,
+                        origin: error.get_origin().or(new_body[i].1.as_ref()).cloned(),
+                    });
                 }
+
+                let (content, origins): (String, Vec<_>) = newer_body
+                    .into_iter()
+                    .unzip();
+
+                scope.content = content;
+                scope.origins = fill_origins(origins);
+
+                Some(scope)
             },
             None => None
         }
     }
+}
 
+impl<'a> Pass<'a> for MacroCompiler<'a> {
+    fn apply(self: Box<Self>, mut scopes: Scopes<'a>) -> Scopes<'a> {
+        let mut compiler = *self;
+        Box::new(std::iter::from_fn(move || compiler.next_scope(&mut scopes)))
+    }
+}
 
+#[deprecated(note = "use .pass(MacroCompiler::new()) instead")]
+pub trait MacroCompilerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Compiles generate function stuff
+    fn macros(self) -> Scopes<'a> {
+        self.pass(MacroCompiler::new())
+    }
 }
 
+#[allow(deprecated)]
+impl<'a, I> MacroCompilerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Contents of compiled scopes, and reported errors with their origins
+    fn compile(content: &str) -> (Vec<String>, Vec<String>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+        scope.origins = (1..=content.lines().count())
+            .map(|line| Origin::new(String::from("main.mcf"), line))
+            .collect();
+
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&errors);
+        let compiler = MacroCompiler::new()
+            .max_depth(8)
+            .report(Box::new(move |diagnostic| found.borrow_mut().push(format!("{}: {}", diagnostic.origin.unwrap(), diagnostic.message))));
+
+        let contents = Some(scope)
+            .into_iter()
+            .pass(compiler)
+            .map(|scope| scope.content)
+            .collect();
+        let errors = errors.borrow().clone();
+        (contents, errors)
+    }
+
+    #[test]
+    fn pastes_recursive_macro() {
+        let content = "call count 2\ngenerate function count $i\nwith recursion\nwith until $i < 0\n\tsay $i\n\tcall count ($i-1)";
+
+        assert_eq!(compile(content), (vec![String::from("say 2\nsay 1\nsay 0\n")], vec![]));
+    }
+
+    #[test]
+    fn reports_errors_at_calling_line() {
+        let content = "say a\ncall missing\ncall loop\nsay b\ngenerate function loop\n\tcall loop";
+
+        assert_eq!(compile(content), (vec![String::from("say a\nsay b\n")], vec![
+            String::from("main.mcf:2: Missing macro definition for \"missing\""),
+            String::from("main.mcf:3: Macro cycle detected: loop -> loop"),
+        ]));
+    }
+
+    #[test]
+    fn stops_recursive_scoped_macro() {
+        let content = "call count 1\ngenerate function count $i\nwith scope\nwith recursion\nwith until $i < 0\n\tsay $i\n\tcall count ($i-1)";
+        let (contents, errors) = compile(content);
+
+        assert_eq!(contents.len(), 3);
+        assert!(contents[2].starts_with("say 0\n") && !contents[2].contains("function"));
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn limits_depth_of_scoped_macros() {
+        let content = "call count 1\ngenerate function count $i\nwith scope\nwith recursion\n\tcall count ($i+1)";
+        let (contents, errors) = compile(content);
+
+        assert_eq!(contents.len(), 9);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("pasted by count 1 at main.mcf:1: Macro expansion exceeded 8 nested calls: count 1 -> count 2 -> count 3 -> count 4 -> count 5 -> count 6 -> count 7 -> count 8 -> count 9"));
+    }
+
+    #[test]
+    fn calls_only_as_command() {
+        let content = "say please call me\nexecute as @a run call greet\ngenerate function greet\n\tsay hi";
+
+        assert_eq!(compile(content), (vec![String::from("say please call me\nexecute as @a run function\n\tsay hi\n")], vec![]));
+    }
+
+    #[test]
+    fn reports_wrong_number_of_arguments() {
+        let content = "call greet\ncall greet a b c\ncall greet a\ngenerate function greet $who $greeting=hi\n\tsay $greeting $who";
+
+        assert_eq!(compile(content), (vec![String::from("say hi a\n")], vec![
+            String::from("main.mcf:1: Missing argument $who of macro \"greet\""),
+            String::from("main.mcf:2: Macro \"greet\" takes 2 argument(s), found 3"),
+        ]));
+    }
+
+    #[test]
+    fn reports_invalid_conditions() {
+        let content = "call a x\ngenerate function a $x\nwith until $x < 1\n\tsay $x\ngenerate function b\nwith until oops\n\tsay b";
+
+        assert_eq!(compile(content).1, vec![
+            String::from("main.mcf:5: Invalid condition \"oops\" of macro \"b\""),
+            String::from("main.mcf:1: Operator \"<\" can only compare numbers, found \"x\" and \"1\""),
+        ]);
+    }
+
+    #[test]
+    fn parses_condition() {
+        let condition = MacroCondition::parse("$i >= 10").unwrap();
+
+        assert_eq!(condition.left, "$i");
+        assert_eq!(condition.operator, ">=");
+        assert_eq!(condition.right, "10");
+    }
+
+    #[test]
+    fn rejects_unknown_operator() {
+        assert_eq!(MacroCondition::parse("$i => 10"), None);
+        assert_eq!(MacroCondition::parse("$i === 10"), None);
+        assert_eq!(MacroCondition::parse("$i is 10"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_condition() {
+        assert_eq!(MacroCondition::parse("$i <"), None);
+        assert_eq!(MacroCondition::parse("$i < 10 extra"), None);
+        assert_eq!(MacroCondition::parse(""), None);
+    }
+
+    #[test]
+    fn compares_numbers() {
+        let condition = MacroCondition::parse("$i < $n").unwrap();
+
+        assert_eq!(condition.is_met("3", "10"), Ok(true));
+        assert_eq!(condition.is_met("10", "3"), Ok(false));
+        assert_eq!(MacroCondition::parse("$i = 3").unwrap().is_met("3", "03"), Ok(true));
+        assert_eq!(MacroCondition::parse("$i != 3").unwrap().is_met("4", "3"), Ok(true));
+    }
+
+    #[test]
+    fn compares_words_for_equality() {
+        assert_eq!(MacroCondition::parse("$a == done").unwrap().is_met("done", "done"), Ok(true));
+        assert_eq!(MacroCondition::parse("$a != done").unwrap().is_met("go", "done"), Ok(true));
+    }
+
+    #[test]
+    fn ordering_words_is_error() {
+        assert!(MacroCondition::parse("$a < b").unwrap().is_met("a", "b").is_err());
+    }
+}
@@ -5,3 +5,13 @@ pub mod substitutions_compiler;
 pub mod macro_compiler;
 pub mod comment_remover;
 pub mod back_compiler;
+pub mod indentation_normalizer;
+pub mod scope_deduplicator;
+pub mod scope_inliner;
+pub mod dead_scope_eliminator;
+pub mod source_header_writer;
+pub mod command_validator;
+pub mod reference_validator;
+pub mod pass;
+pub mod constants_compiler;
+pub mod conditional_compiler;
@@ -0,0 +1,40 @@
+use crate::mcfrs::scope::Scope;
+
+pub type Scopes<'a> = Box<dyn Iterator<Item = Scope<'a>> + 'a>;
+
+// Step of compilation, turning stream of scopes into another one
+// Closures taking and returning Scopes are passes as well
+pub trait Pass<'a> {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a>;
+}
+
+impl<'a, F> Pass<'a> for F
+where F: FnOnce(Scopes<'a>) -> Scopes<'a> {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        (*self)(scopes)
+    }
+}
+
+// Pass that changes every scope on its own, without writing an iterator adapter
+pub struct ScopeMap<F>(pub F);
+
+impl<'a, F> Pass<'a> for ScopeMap<F>
+where F: FnMut(Scope<'a>) -> Scope<'a> + 'a {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(self.0))
+    }
+}
+
+pub trait PassExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Runs pass on scopes, so passes can be chained one after another
+    fn pass<P>(self, pass: P) -> Scopes<'a>
+    where P: Pass<'a> + 'a;
+}
+
+impl<'a, I> PassExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {
+    fn pass<P>(self, pass: P) -> Scopes<'a>
+    where P: Pass<'a> + 'a {
+        Box::new(pass).apply(Box::new(self))
+    }
+}
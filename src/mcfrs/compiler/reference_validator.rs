@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use crate::{mcfrs::{call_graph::get_calls, compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, source_map::SourceMap}, vanilla::{datapack::Datapack, function::Function, function_tag::FunctionTag}};
+
+// Warns about "function <reference>" and function tag values, that are not compiled scopes,
+// .mcfunction files, function tags or declared externals
+// Needs every scope before it can return the first one
+pub struct ReferenceValidator<'a> {
+    datapack: &'a Datapack,
+    externals: Vec<String>,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
+}
+
+// Reference without namespace is in minecraft namespace
+fn resolve(reference: &str) -> String {
+    let (tag, reference) = match reference.strip_prefix('#') {
+        Some(reference) => ("#", reference),
+        None => ("", reference),
+    };
+
+    match reference.contains(':') {
+        true => format!("{}{}", tag, reference),
+        false => format!("{}minecraft:{}", tag, reference),
+    }
+}
+
+impl<'a> ReferenceValidator<'a> {
+    // Functions and tags of datapack are checked, even if none of its scopes are compiled
+    pub fn new(datapack: &'a Datapack) -> Self {
+        Self {
+            datapack,
+            externals: Vec::new(),
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
+
+    // Function provided by other datapack, like ns:name, or ns:* for whole namespace
+    pub fn external(mut self, reference: String) -> Self {
+        self.externals.push(reference);
+        self
+    }
+
+    // Called with every problem found, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
+
+    fn is_external(&self, reference: &str) -> bool {
+        self.externals
+            .iter()
+            .any(|external| match external.strip_suffix('*') {
+                Some(prefix) => reference.starts_with(prefix),
+                None => reference == external,
+            })
+    }
+
+    fn validate(&mut self, scopes: &[Scope<'a>]) {
+        let datapack = self.datapack;
+
+        // .mcfunction files written by the previous build are not known, they may not be compiled any more
+        let burned = SourceMap::read(&SourceMap::get_path(datapack))
+            .map(|source_map| source_map.functions)
+            .unwrap_or_default();
+        let written = Function::list_all(datapack, "mcfunction")
+            .into_iter()
+            .filter(|reference| !burned.contains_key(reference));
+
+        let tags = FunctionTag::read_all(datapack);
+        let known = scopes
+            .iter()
+            .map(|scope| scope.get_reference_name())
+            .chain(Function::list_all(datapack, "mcf"))
+            .chain(written)
+            .chain(tags.iter().map(|tag| format!("#{}", tag.name)))
+            .collect::<HashSet<_>>();
+
+        for scope in scopes {
+            for (i, line) in scope.content.lines().enumerate() {
+                for (_, reference) in get_calls(line) {
+                    let reference = resolve(reference);
+
+                    if !known.contains(&reference) && !self.is_external(&reference) {
+                        (self.report)(Diagnostic::new(Severity::Warning, format!("Function \"{}\" does not exist", reference), scope, i));
+                    }
+                }
+            }
+        }
+
+        // Tags are not compiled, so there is no line to point at
+        for tag in tags.iter() {
+            for (i, value) in tag.values.iter().enumerate() {
+                let reference = resolve(&value.id);
+
+                if value.required && !known.contains(&reference) && !self.is_external(&reference) {
+                    (self.report)(Diagnostic {
+                        severity: Severity::Warning,
+                        message: format!("Function \"{}\" does not exist", reference),
+                        function: format!("#{}", tag.name),
+                        line: i + 1,
+                        origin: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Pass<'a> for ReferenceValidator<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(std::iter::once_with(move || {
+            let scopes = scopes.collect::<Vec<_>>();
+            self.validate(&scopes);
+            scopes
+        }).flatten())
+    }
+}
+
+#[deprecated(note = "use .pass(ReferenceValidator::new(datapack)) instead")]
+pub trait ReferenceValidatorExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Warns about "function <reference>" and function tag values, that are not defined anywhere
+    fn validate_references(self, datapack: &'a Datapack) -> Scopes<'a> {
+        self.pass(ReferenceValidator::new(datapack))
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> ReferenceValidatorExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::vanilla::namespace::Namespace;
+
+    use super::*;
+
+    // Empty datapack in temporary directory, with given files written into it
+    fn create_datapack(name: &str, files: &[(&str, &str)]) -> Datapack {
+        let root = std::env::temp_dir().join(format!("mcfrs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let mut datapack = Datapack::try_new(String::from(name)).unwrap();
+        datapack.path = root;
+
+        for (path, content) in files {
+            let path = datapack.path.join(name).join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        datapack
+    }
+
+    // Messages of warnings found in content of ns:main
+    fn validate(datapack: &Datapack, content: Option<&str>, externals: &[&str]) -> Vec<String> {
+        let namespace = Namespace::try_new(datapack, String::from("ns")).unwrap();
+        let scopes = content
+            .map(|content| {
+                let mut scope = Scope::new(String::from("main"), &namespace);
+                scope.content = content.to_string();
+                scope
+            })
+            .into_iter();
+
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&messages);
+        let validator = externals
+            .iter()
+            .fold(ReferenceValidator::new(datapack), |pass, external| pass.external(external.to_string()))
+            .report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.message)));
+
+        scopes.pass(validator).for_each(drop);
+        let messages = messages.borrow().clone();
+        messages
+    }
+
+    #[test]
+    fn accepts_external_namespace() {
+        let datapack = create_datapack("references-externals", &[]);
+        let content = "function other:a/b\nfunction another:c\nfunction ns:main";
+
+        assert_eq!(validate(&datapack, Some(content), &["other:*"]), vec![String::from("Function \"another:c\" does not exist")]);
+    }
+
+    #[test]
+    fn ignores_burned_functions() {
+        let datapack = create_datapack("references-burned", &[
+            ("data/ns/functions/old.mcfunction", "say old"),
+            ("data/ns/functions/written.mcfunction", "say written"),
+            ("mcfrs.sourcemap.json", r#"{"functions": {"ns:old": []}}"#),
+        ]);
+
+        assert_eq!(validate(&datapack, Some("function ns:old\nfunction ns:written"), &[]), vec![String::from("Function \"ns:old\" does not exist")]);
+    }
+
+    #[test]
+    fn checks_tags_without_scopes() {
+        let datapack = create_datapack("references-tags", &[
+            ("data/minecraft/tags/functions/load.json", r#"{"values": ["ns:missing", {"id": "ns:optional", "required": false}]}"#),
+        ]);
+
+        assert_eq!(validate(&datapack, None, &[]), vec![String::from("Function \"ns:missing\" does not exist")]);
+    }
+}
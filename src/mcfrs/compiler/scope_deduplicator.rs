@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, scope::Scope};
+
+// Merges generated scopes with identical content into one
+// Needs every scope before it can return the first one
+#[derive(Default)]
+pub struct ScopeDeduplicator;
+
+impl ScopeDeduplicator {
+    pub fn new() -> Self { Self }
+
+    // Replaces every reference found in renames, references are whole words
+    pub fn rename_references(content: &str, renames: &HashMap<String, String>) -> String {
+        content
+            .split('\n')
+            .map(|line| line
+                .split(' ')
+                .map(|word| match renames.get(word) {
+                    Some(name) => name.as_str(),
+                    None => word,
+                })
+                .join(" "))
+            .join("\n")
+    }
+
+    fn deduplicate<'a>(mut scopes: Vec<Scope<'a>>) -> Vec<Scope<'a>> {
+        // Merging scopes can make scopes calling them identical, so repeat until nothing changes
+        loop {
+            let mut kept: HashMap<&str, String> = HashMap::new();
+            let mut renames: HashMap<String, String> = HashMap::new();
+
+            for scope in scopes.iter().filter(|scope| scope.is_generated()) {
+                match kept.get(scope.content.as_str()) {
+                    Some(name) => { renames.insert(scope.get_reference_name(), name.clone()); },
+                    None => { kept.insert(scope.content.as_str(), scope.get_reference_name()); },
+                }
+            }
+
+            if renames.is_empty() {
+                return scopes;
+            }
+
+            scopes = scopes
+                .into_iter()
+                .filter(|scope| !renames.contains_key(&scope.get_reference_name()))
+                .map(|mut scope| {
+                    scope.content = Self::rename_references(&scope.content, &renames);
+                    scope
+                })
+                .collect();
+        }
+    }
+}
+
+impl<'a> Pass<'a> for ScopeDeduplicator {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(std::iter::once_with(move || Self::deduplicate(scopes.collect())).flatten())
+    }
+}
+
+#[deprecated(note = "use .pass(ScopeDeduplicator::new()) instead")]
+pub trait ScopeDeduplicatorExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Merges generated scopes with identical content into one
+    fn deduplicate(self) -> Scopes<'a> {
+        self.pass(ScopeDeduplicator::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> ScopeDeduplicatorExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Names and contents of deduplicated scopes, given as name and content of main, then of its children
+    fn deduplicate(contents: &[(&str, &str)]) -> Vec<(String, String)> {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut main = Scope::new(String::from(contents[0].0), &namespace);
+        main.content = contents[0].1.to_string();
+
+        let children = contents[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, (name, content))| {
+                let mut scope = Scope::new_child(&main, i);
+                scope.name = name.to_string();
+                scope.content = content.to_string();
+                scope
+            })
+            .collect::<Vec<_>>();
+
+        Some(main)
+            .into_iter()
+            .chain(children)
+            .pass(ScopeDeduplicator::new())
+            .map(|scope| (scope.name, scope.content))
+            .collect()
+    }
+
+    #[test]
+    fn merges_identical_scopes() {
+        let scopes = deduplicate(&[("main", "function ns:a\nfunction ns:b"), ("a", "say x"), ("b", "say x")]);
+
+        assert_eq!(scopes, vec![
+            (String::from("main"), String::from("function ns:a\nfunction ns:a")),
+            (String::from("a"), String::from("say x")),
+        ]);
+    }
+
+    #[test]
+    fn merges_scopes_made_identical() {
+        let scopes = deduplicate(&[
+            ("main", "function ns:a\nfunction ns:b"),
+            ("a", "function ns:c"),
+            ("b", "function ns:d"),
+            ("c", "say x"),
+            ("d", "say x"),
+        ]);
+
+        assert_eq!(scopes, vec![
+            (String::from("main"), String::from("function ns:a\nfunction ns:a")),
+            (String::from("a"), String::from("function ns:c")),
+            (String::from("c"), String::from("say x")),
+        ]);
+    }
+
+    #[test]
+    fn keeps_written_scopes() {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let scopes = ["a", "b"]
+            .iter()
+            .map(|name| {
+                let mut scope = Scope::new(name.to_string(), &namespace);
+                scope.content = String::from("say x");
+                scope
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(scopes.into_iter().pass(ScopeDeduplicator::new()).count(), 2);
+    }
+
+    #[test]
+    fn renames_whole_words() {
+        let renames = vec![(String::from("ns:b"), String::from("ns:a"))].into_iter().collect();
+
+        assert_eq!(ScopeDeduplicator::rename_references("function ns:b\nfunction ns:bb", &renames), "function ns:a\nfunction ns:bb");
+    }
+}
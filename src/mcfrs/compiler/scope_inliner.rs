@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, scope::Scope, util::is_comment};
+
+// Replaces "run function <scope>" with the command, if generated scope has only one command
+// Needs every scope before it can return the first one
+#[derive(Default)]
+pub struct ScopeInliner {
+    exports: Vec<String>,
+}
+
+impl ScopeInliner {
+    pub fn new() -> Self { Self { exports: Vec::new() } }
+
+    // Keeps function after its calls are inlined, as something outside of the datapack, or a function tag, calls it.
+    // Reference is like ns:name
+    pub fn export(mut self, reference: String) -> Self {
+        self.exports.push(reference);
+        self
+    }
+
+    // Pastes command in place of "function <reference>" at the end of line
+    // Returns None if line does not call any of commands, or calling it can not be replaced
+    fn inline_line(line: &str, commands: &HashMap<String, String>) -> Option<String> {
+        if is_comment(line) {
+            return None;
+        }
+
+        let words = line.split(' ').collect::<Vec<_>>();
+        let (reference, keyword, prefix) = match words.as_slice() {
+            [prefix @ .., keyword, reference] => (*reference, *keyword, prefix),
+            _ => return None,
+        };
+
+        let command = commands.get(reference)?;
+
+        // "execute store" would store result of the command instead of the function
+        let is_inlinable = keyword == "function"
+            && prefix.last().map(|&word| word == "run").unwrap_or(true)
+            && !prefix.contains(&"store");
+
+        if !is_inlinable {
+            return None;
+        }
+
+        // execute ... run execute ... -> execute ... ...
+        match (prefix, command.strip_prefix("execute ")) {
+            ([prefix @ .., _], Some(subcommands)) => Some(format!("{} {}", prefix.join(" "), subcommands)),
+            _ => Some(prefix.iter().chain(Some(&command.as_str())).join(" ")),
+        }
+    }
+
+    fn inline<'a>(&self, mut scopes: Vec<Scope<'a>>) -> Vec<Scope<'a>> {
+        // Inlined command can make its parent single command scope as well
+        loop {
+            let commands = scopes
+                .iter()
+                .filter(|scope| scope.is_generated())
+                .filter_map(|scope| {
+                    let reference = scope.get_reference_name();
+                    let command = scope.content
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty() && !is_comment(line))
+                        .exactly_one()
+                        .ok()?;
+
+                    match command.split(' ').any(|word| word == reference) {
+                        true => None,
+                        false => Some((reference, command.to_string())),
+                    }
+                })
+                .collect::<HashMap<_, _>>();
+
+            let mut inlined: HashSet<String> = HashSet::new();
+
+            for scope in scopes.iter_mut() {
+                scope.content = scope.content
+                    .split('\n')
+                    .map(|line| match Self::inline_line(line, &commands) {
+                        Some(new_line) => {
+                            inlined.insert(line.split(' ').next_back().unwrap().to_string());
+                            new_line
+                        },
+                        None => line.to_string(),
+                    })
+                    .join("\n");
+            }
+
+            if inlined.is_empty() {
+                return scopes;
+            }
+
+            let referenced = scopes
+                .iter()
+                .flat_map(|scope| scope.content.split_whitespace())
+                .filter(|word| inlined.contains(*word))
+                .map(|word| word.to_string())
+                .collect::<HashSet<_>>();
+
+            scopes.retain(|scope| {
+                let reference = scope.get_reference_name();
+                !inlined.contains(&reference) || referenced.contains(&reference) || self.exports.contains(&reference)
+            });
+        }
+    }
+}
+
+impl<'a> Pass<'a> for ScopeInliner {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(std::iter::once_with(move || self.inline(scopes.collect())).flatten())
+    }
+}
+
+#[deprecated(note = "use .pass(ScopeInliner::new()) instead")]
+pub trait ScopeInlinerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Replaces "run function <scope>" with the command, if generated scope has only one command
+    fn inline(self) -> Scopes<'a> {
+        self.pass(ScopeInliner::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> ScopeInlinerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use crate::vanilla::{datapack::Datapack, namespace::Namespace};
+
+    use super::*;
+
+    // Names and contents of scopes left, given as content of ns:main, then of its generated scopes ns:a, ns:b...
+    fn inline(contents: &[&str], inliner: ScopeInliner) -> Vec<(String, String)> {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut main = Scope::new(String::from("main"), &namespace);
+        main.content = contents[0].to_string();
+
+        let generated = contents[1..]
+            .iter()
+            .zip("abc".chars())
+            .enumerate()
+            .map(|(i, (content, name))| {
+                let mut scope = Scope::new_child(&main, i);
+                scope.name = name.to_string();
+                scope.content = content.to_string();
+                scope
+            })
+            .collect::<Vec<_>>();
+
+        let scopes = Some(main)
+            .into_iter()
+            .chain(generated)
+            .pass(inliner)
+            .map(|scope| (scope.name, scope.content))
+            .collect();
+        scopes
+    }
+
+    #[test]
+    fn inlines_single_command() {
+        assert_eq!(inline(&["execute as @a run function ns:a", "say hi"], ScopeInliner::new()), vec![
+            (String::from("main"), String::from("execute as @a run say hi")),
+        ]);
+    }
+
+    #[test]
+    fn keeps_stored_function() {
+        let content = "execute store result score @s a run function ns:a";
+
+        assert_eq!(inline(&[content, "say hi"], ScopeInliner::new()), vec![
+            (String::from("main"), String::from(content)),
+            (String::from("a"), String::from("say hi")),
+        ]);
+    }
+
+    #[test]
+    fn merges_nested_execute() {
+        let contents = ["execute as @a run function ns:a", "execute at @s run function ns:b", "say hi"];
+
+        assert_eq!(inline(&contents, ScopeInliner::new()), vec![
+            (String::from("main"), String::from("execute as @a at @s run say hi")),
+        ]);
+    }
+
+    #[test]
+    fn keeps_exported_scope() {
+        let inliner = ScopeInliner::new().export(String::from("ns:a"));
+
+        assert_eq!(inline(&["execute as @a run function ns:a", "say hi"], inliner), vec![
+            (String::from("main"), String::from("execute as @a run say hi")),
+            (String::from("a"), String::from("say hi")),
+        ]);
+    }
+}
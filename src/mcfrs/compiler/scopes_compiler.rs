@@ -2,82 +2,75 @@ use std::{iter::Peekable, ops::RangeFrom};
 
 use itertools::Itertools;
 
-use crate::mcfrs::scope::Scope;
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, scope::{Scope, ScopeNaming}, source_map::{fill_origins, Origin}, util::is_comment};
 
-pub struct ScopesCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>>{
-    source: I,
-
-    buffered: Vec<Scope<'a>>,
+// Converts indentations into scopes
+pub struct ScopesCompiler {
     next_anonymous_scope_name: RangeFrom<usize>,
+    naming: ScopeNaming,
 }
 
-impl<'a, I> Iterator for ScopesCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    type Item = Scope<'a>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.buffered.pop() {
-            None => match self.source.next() {
-                Some(mut scope) => {
-                    let lines = scope
-                        .content
-                        .clone();
-                    let lines = lines
-                        .lines()
-                        .peekable();
-                    let mut new_scopes: Vec<Scope<'a>> = Vec::new();
-
-                    self.generate_scopes(&mut scope, lines, &mut new_scopes);
-                    self.buffered = new_scopes;
-                    Some(scope)
-                }
-                None => None
-            },
-            scope => scope
-        }
-    }
-}
-
-pub trait ScopesCompilerExt<'a>: Sized + Iterator<Item = Scope<'a>> {
-    // Converts indentations into scopes
-    fn scopes(self) -> ScopesCompiler<'a, Self>;
-}
-
-impl<'a, I> ScopesCompilerExt<'a> for I
-where I: Iterator<Item = Scope<'a>> {
-    fn scopes(self) -> ScopesCompiler<'a, Self> { ScopesCompiler::new(self) }
+impl Default for ScopesCompiler {
+    fn default() -> Self { Self::new() }
 }
 
-impl<'a, I> ScopesCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    pub fn new(source: I) -> Self {
+impl ScopesCompiler {
+    pub fn new() -> Self {
         Self {
-            source,
-            buffered: Vec::new(),
-            next_anonymous_scope_name: (0 as usize)..,
+            next_anonymous_scope_name: 0..,
+            naming: ScopeNaming::default(),
         }
     }
 
-    pub fn generate_scopes<'b, J>(&mut self, scope: &mut Scope<'a>, mut lines: Peekable<J>, new_scopes: &mut Vec<Scope<'a>>) -> ()
-    where J: Iterator<Item = &'b str> {
+    pub fn naming(mut self, naming: ScopeNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    pub fn generate_scopes<'a, 'b, J>(&mut self, scope: &mut Scope<'a>, mut lines: Peekable<J>, new_scopes: &mut Vec<Scope<'a>>)
+    where J: Iterator<Item = (&'b str, Option<Origin>)> {
         let mut new_content = String::new();
+        let mut origins: Vec<Option<Origin>> = Vec::new();
+        let mut position = 0..;
 
         while lines.peek().is_some() {
-            new_content += lines
+            let (mut code, mut code_origins): (Vec<_>, Vec<_>) = lines
                 .by_ref()
-                .peeking_take_while(|line| line.chars().next() != Some('\t'))
-                .join("\n")
-                .as_str();
+                .peeking_take_while(|(line, _)| !line.starts_with('\t'))
+                .unzip();
 
             let new_scope = lines
                 .by_ref()
-                .peeking_take_while(|line| line.chars().next() == Some('\t'))
-                .map(|line| &line[1..])
+                .peeking_take_while(|(line, _)| line.starts_with('\t'))
+                .map(|(line, origin)| (&line[1..], origin))
                 .collect::<Vec<_>>();
 
-            if new_scope.len() > 0 {
-                let mut scope = Scope::new_unnamed(self.next_anonymous_scope_name.next().unwrap(), scope.namespace);
+            // Kept comments between line and its block go above the line, that the reference is appended to
+            let head = code.iter().rposition(|line| !is_comment(line));
+            if let (Some(head), false) = (head, new_scope.is_empty()) {
+                code[head..].rotate_left(1);
+                code_origins[head..].rotate_left(1);
+            }
+
+            new_content += code
+                .join("\n")
+                .as_str();
+            origins.append(&mut code_origins);
+
+            if !new_scope.is_empty() {
+                // Reference is appended to the line above, unless there is no line above
+                if head.is_none() {
+                    if !code.is_empty() {
+                        new_content += "\n";
+                    }
+                    origins.push(new_scope[0].1.clone());
+                }
+
+                let mut scope = match self.naming {
+                    ScopeNaming::Hex => Scope::new_unnamed(self.next_anonymous_scope_name.next().unwrap(), scope.namespace)
+                        .with_parent(scope),
+                    ScopeNaming::Readable => Scope::new_child(scope, position.next().unwrap()),
+                };
 
                 new_content += " ";
                 new_content += scope
@@ -91,6 +84,81 @@ where I: Iterator<Item = Scope<'a>> {
         }
 
         scope.content = new_content;
+        scope.origins = fill_origins(origins);
+    }
+
+    // Scope itself comes first, then scopes made from its indented blocks
+    fn compile<'a>(&mut self, mut scope: Scope<'a>) -> Vec<Scope<'a>> {
+        let lines = scope
+            .content
+            .clone();
+        let origins = std::mem::take(&mut scope.origins);
+        let lines = lines
+            .lines()
+            .zip(origins.into_iter().map(Some).chain(std::iter::repeat(None)))
+            .peekable();
+        let mut new_scopes: Vec<Scope<'a>> = Vec::new();
+
+        self.generate_scopes(&mut scope, lines, &mut new_scopes);
+
+        std::iter::once(scope)
+            .chain(new_scopes.into_iter().rev())
+            .collect()
+    }
+}
+
+impl<'a> Pass<'a> for ScopesCompiler {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.flat_map(move |scope| self.compile(scope)))
+    }
+}
+
+#[deprecated(note = "use .pass(ScopesCompiler::new()) instead")]
+pub trait ScopesCompilerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Converts indentations into scopes
+    fn scopes(self) -> Scopes<'a> {
+        self.pass(ScopesCompiler::new())
     }
 }
 
+#[allow(deprecated)]
+impl<'a, I> ScopesCompilerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mcfrs::compiler::pass::PassExt, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Names and contents of compiled scopes
+    fn compile(content: &str) -> Vec<(String, String)> {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let scopes = Some(scope)
+            .into_iter()
+            .pass(ScopesCompiler::new())
+            .map(|scope| (scope.name, scope.content))
+            .collect();
+        scopes
+    }
+
+    #[test]
+    fn appends_reference_to_line_above() {
+        assert_eq!(compile("execute as @a run function\n\tsay a\nsay b"), vec![
+            (String::from("main"), String::from("execute as @a run function ns:_/00/00/00/00\nsay b")),
+            (String::from("_/00/00/00/00"), String::from("say a")),
+        ]);
+    }
+
+    #[test]
+    fn skips_kept_comments() {
+        assert_eq!(compile("execute as @a run function\n# note\n\tsay a"), vec![
+            (String::from("main"), String::from("# note\nexecute as @a run function ns:_/00/00/00/00\n")),
+            (String::from("_/00/00/00/00"), String::from("say a")),
+        ]);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, scope::Scope};
+
+// Adds "# Compiled from <file>:<line>" comment at the top of every scope
+#[derive(Default)]
+pub struct SourceHeaderWriter;
+
+impl SourceHeaderWriter {
+    pub fn new() -> Self { Self }
+
+    fn write<'a>(&self, mut scope: Scope<'a>) -> Scope<'a> {
+        scope.content = format!("# Compiled from {}\n{}", scope.get_source_location(), scope.content);
+
+        // Header points to the same place as the first line
+        if let Some(origin) = scope.origins.first().cloned() {
+            scope.origins.insert(0, origin);
+        }
+
+        scope
+    }
+}
+
+impl<'a> Pass<'a> for SourceHeaderWriter {
+    fn apply(self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.write(scope)))
+    }
+}
+
+#[deprecated(note = "use .pass(SourceHeaderWriter::new()) instead")]
+pub trait SourceHeaderWriterExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Adds "# Compiled from <file>:<line>" comment at the top of every scope
+    fn source_headers(self) -> Scopes<'a> {
+        self.pass(SourceHeaderWriter::new())
+    }
+}
+
+#[allow(deprecated)]
+impl<'a, I> SourceHeaderWriterExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
@@ -1,79 +1,157 @@
 use regex::Regex;
-use sha2::Digest;
 
-use crate::mcfrs::scope::Scope;
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
-pub struct SubstitutionsCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    source: I,
+use crate::mcfrs::{compiler::pass::{Pass, PassExt, Scopes}, diagnostic::{Diagnostic, Severity}, scope::Scope, util::{HashedNames, Hasher}};
+
+// Converts:
+// $this -> current scope reference name
+// score@board -> score board
+// #[some_value] -> first 16 base32 chars of sha256, unless set otherwise with hasher
+pub struct SubstitutionsCompiler<'a> {
+    hasher: Hasher,
+    // Shared with other namespaces of a build, to find collisions between them as well
+    hashed_names: HashedNames,
     score_regex: Regex,
     hash_regex: Regex,
+    report: Box<dyn FnMut(Diagnostic) + 'a>,
 }
 
-impl<'a, I> Iterator for SubstitutionsCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    type Item = Scope<'a>;
+impl<'a> Default for SubstitutionsCompiler<'a> {
+    fn default() -> Self { Self::new() }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.source.next() {
-            Some(mut scope) => {
-                scope.content = scope.content.replace("$this", &scope.get_reference_name());
-                scope.content = scope.content.replace("$namespace", &scope.namespace.name);
+impl<'a> SubstitutionsCompiler<'a> {
+    pub fn new() -> Self {
+        Self {
+            hasher: Hasher::new(),
+            hashed_names: Rc::new(RefCell::new(BTreeMap::new())),
+            score_regex: Regex::new(r"([a-z0-9\\-_]+)@([a-z0-9\\-_]+)").unwrap(),
+            hash_regex: Regex::new(r"#\[([a-z0-9\-_.]+)\]").unwrap(),
+            report: Box::new(|diagnostic| eprintln!("{}", diagnostic)),
+        }
+    }
 
-                for capture in self.hash_regex.captures_iter(&scope.content.clone()) {
-                    let full_match = &capture[0];
-                    let unhashed_value = &capture[1];
+    pub fn hasher(mut self, hasher: Hasher) -> Self {
+        self.hasher = hasher;
+        self
+    }
 
-                    let mut hasher = sha2::Sha256::new();
-                    hasher.update(unhashed_value);
+    // Every #[value] hashed is added there
+    pub fn hashed_names(mut self, hashed_names: HashedNames) -> Self {
+        self.hashed_names = hashed_names;
+        self
+    }
 
-                    let hashed_value = hasher.finalize();
-                    let hashed_value = data_encoding::BASE32.encode(&hashed_value);
-                    let hashed_value = &hashed_value[0..16];
-                    let hashed_value = hashed_value.to_lowercase();
+    // Called with every value hashed to the same name as other value, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = report;
+        self
+    }
 
-                    scope.content = scope.content.replace(full_match, &hashed_value);
-                }
+    fn compile(&mut self, mut scope: Scope<'a>) -> Scope<'a> {
+        scope.content = scope.content.replace("$this", &scope.get_reference_name());
+        scope.content = scope.content.replace("$namespace", &scope.namespace.name);
 
-                for capture in self.score_regex.captures_iter(&scope.content.clone()) {
-                    let full_match = &capture[0];
-                    let score_name = &capture[1];
-                    let score_objective = &capture[2];
+        for capture in self.hash_regex.captures_iter(&scope.content.clone()) {
+            let full_match = &capture[0];
+            let unhashed_value = &capture[1];
 
-                    scope.content = scope.content.replace(full_match, format!("{} {}", score_name, score_objective).as_str());
-                }
+            let hashed_value = self.hasher.hash(unhashed_value);
 
-                Some(scope)
+            let previous = self.hashed_names
+                .borrow_mut()
+                .entry(hashed_value.clone())
+                .or_insert_with(|| unhashed_value.to_string())
+                .clone();
+
+            if previous != unhashed_value {
+                let line = scope.content
+                    .lines()
+                    .position(|line| line.contains(full_match))
+                    .unwrap_or_default();
+                let message = format!("#[{}] and #[{}] are both hashed to \"{}\", use longer hash or other salt",
+                    unhashed_value, previous, hashed_value);
+
+                (self.report)(Diagnostic::new(Severity::Error, message, &scope, line));
             }
-            None => None,
+
+            scope.content = scope.content.replace(full_match, &hashed_value);
         }
-    }
-}
 
-impl<'a, I> SubstitutionsCompiler<'a, I>
-where I: Iterator<Item = Scope<'a>> {
-    pub fn new(source: I) -> Self { 
-        Self {
-            source,
-            score_regex: Regex::new(r"([a-z0-9\\-_]+)@([a-z0-9\\-_]+)").unwrap(),
-            hash_regex: Regex::new(r"#\[([a-z0-9\-_.]+)\]").unwrap(),
+        for capture in self.score_regex.captures_iter(&scope.content.clone()) {
+            let full_match = &capture[0];
+            let score_name = &capture[1];
+            let score_objective = &capture[2];
+
+            scope.content = scope.content.replace(full_match, format!("{} {}", score_name, score_objective).as_str());
         }
+
+        scope
     }
 }
 
-pub trait SubstitutionsCompilerExt<'a, I>: Sized + Iterator<Item = Scope<'a>> 
-where I: Iterator<Item = Scope<'a>> {
-    // Converts:
-    // $this -> current scope reference name
-    // score@board -> score board
-    // #[some_value] -> first 16 base32 chars of sha256
-    fn substitutions(self) -> SubstitutionsCompiler<'a, I>;
+impl<'a> Pass<'a> for SubstitutionsCompiler<'a> {
+    fn apply(mut self: Box<Self>, scopes: Scopes<'a>) -> Scopes<'a> {
+        Box::new(scopes.map(move |scope| self.compile(scope)))
+    }
 }
 
-impl<'a, I> SubstitutionsCompilerExt<'a, I> for I
-where I: Iterator<Item = Scope<'a>> {
-    fn substitutions(self) -> SubstitutionsCompiler<'a, I> {
-        SubstitutionsCompiler::new(self)
+#[deprecated(note = "use .pass(SubstitutionsCompiler::new()) instead")]
+pub trait SubstitutionsCompilerExt<'a>: Sized + Iterator<Item = Scope<'a>> + 'a {
+    // Converts $this, score@board and #[some_value]
+    fn substitutions(self) -> Scopes<'a> {
+        self.pass(SubstitutionsCompiler::new())
     }
 }
 
+#[allow(deprecated)]
+impl<'a, I> SubstitutionsCompilerExt<'a> for I
+where I: Iterator<Item = Scope<'a>> + 'a {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mcfrs::{compiler::pass::PassExt, util::HashEncoding}, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+    use super::*;
+
+    // Substituted content, and lines of reported errors
+    fn compile(content: &str, compiler: SubstitutionsCompiler) -> (String, Vec<usize>) {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::clone(&lines);
+        let compiler = compiler.report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic.line)));
+
+        let content = Some(scope).into_iter().pass(compiler).next().unwrap().content;
+        let lines = lines.borrow().clone();
+        (content, lines)
+    }
+
+    #[test]
+    fn substitutes_names_and_scores() {
+        let content = "function $this\nscoreboard players set $namespace.a@#[x] 1";
+
+        assert_eq!(compile(content, SubstitutionsCompiler::new()), (String::from("function ns:main\nscoreboard players set ns.a fvyrmqvxe2yeialc 1"), vec![]));
+    }
+
+    #[test]
+    fn records_hashed_names() {
+        let hashed_names: HashedNames = Rc::default();
+        compile("say #[a] #[b]", SubstitutionsCompiler::new().hashed_names(Rc::clone(&hashed_names)));
+
+        assert_eq!(hashed_names.borrow().values().collect::<Vec<_>>(), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn reports_collisions() {
+        // "v3" and "v5" both start with "e" in hex
+        let hasher = Hasher::new().encoding(HashEncoding::Hex).length(1);
+        let content = "say #[v3]\nsay #[v5]";
+
+        assert_eq!(compile(content, SubstitutionsCompiler::new().hasher(hasher)), (String::from("say e\nsay e"), vec![2]));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::{mcfrs::{compiler::indentation_normalizer::Indentation, diagnostic::Severity, compiler::{conditional_compiler::DEFAULT_PROFILE, constants_compiler::is_constant_name_valid}, lint::{parse_level, LintRule}, scope::ScopeNaming, util::{HashEncoding, Hasher}}, vanilla::{datapack::{Datapack, McVersion}, namespace::Namespace}};
+
+pub const CONFIG_FILE: &str = "mcfrs.toml";
+
+// Project configuration read from mcfrs.toml, every key is optional
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub datapack: DatapackConfig,
+    pub build: BuildConfig,
+    pub passes: PassesConfig,
+    pub hash: HashConfig,
+    // Substituted for $name in every file, strings are used as they are
+    pub constants: BTreeMap<String, toml::Value>,
+    // Rule name to "allow", "warning" or "error"
+    pub lint: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatapackConfig {
+    pub name: String,
+    pub description: String,
+    // Minecraft version, like "1.17.4"
+    pub version: String,
+    // Directory containing the datapack, compiled functions are written there
+    pub path: PathBuf,
+    pub namespaces: Vec<String>,
+}
+
+impl Default for DatapackConfig {
+    fn default() -> Self {
+        Self {
+            name: String::from("test"),
+            description: String::new(),
+            version: String::from(McVersion::V1_17_4.get_name()),
+            path: PathBuf::from("."),
+            namespaces: vec![String::from("ns")],
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum IndentationConfig {
+    // "tabs" or "infer"
+    Name(String),
+    // Number of spaces
+    Width(usize),
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    pub indentation: IndentationConfig,
+    pub keep_comments: bool,
+    pub source_headers: bool,
+    // Functions from other datapacks, like ns:name or ns:*
+    pub externals: Vec<String>,
+    // Generated functions called from outside of the datapack, like ns:name, kept even if nothing calls them
+    pub exports: Vec<String>,
+    // Line width used by fmt
+    pub width: Option<usize>,
+    // Checked by "#if profile == <name>", like "debug" or "release"
+    pub profile: String,
+    // build writes function, that sets display names of hashed objectives to their values
+    pub debug_names: bool,
+    // How indented blocks are named, "hex" or "readable"
+    pub scope_naming: String,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            indentation: IndentationConfig::Name(String::from("infer")),
+            keep_comments: false,
+            source_headers: false,
+            externals: Vec::new(),
+            exports: Vec::new(),
+            width: None,
+            profile: String::from(DEFAULT_PROFILE),
+            debug_names: false,
+            scope_naming: String::from(ScopeNaming::default().get_name()),
+        }
+    }
+}
+
+// Passes of the pipeline, that can be turned off
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassesConfig {
+    pub validate_commands: bool,
+    pub deduplicate: bool,
+    pub inline: bool,
+    pub eliminate_dead_scopes: bool,
+    pub validate_references: bool,
+}
+
+impl Default for PassesConfig {
+    fn default() -> Self {
+        Self {
+            validate_commands: true,
+            deduplicate: true,
+            inline: true,
+            eliminate_dead_scopes: true,
+            validate_references: true,
+        }
+    }
+}
+
+// How #[value] is hashed
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HashConfig {
+    pub length: usize,
+    // "base32", "base32hex", "hex" or "base64url"
+    pub encoding: String,
+    pub salt: String,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            encoding: String::from(HashEncoding::default().get_name()),
+            salt: String::new(),
+        }
+    }
+}
+
+impl Config {
+    // Default config, if there is no file at path
+    pub fn read(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content).map_err(|message| format!("{}: {}", path.display(), message)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|error| error.to_string().trim_end().to_string())
+    }
+
+    pub fn get_datapack(&self) -> Result<Datapack, String> {
+        let mut datapack = Datapack::try_new(self.datapack.name.clone())
+            .map_err(|_| format!("Invalid datapack name \"{}\"", self.datapack.name))?;
+
+        datapack.version = McVersion::parse(&self.datapack.version)
+            .ok_or_else(|| format!("Unsupported version \"{}\"", self.datapack.version))?;
+        datapack.description = self.datapack.description.clone();
+        datapack.path = self.datapack.path.clone();
+
+        Ok(datapack)
+    }
+
+    pub fn get_namespaces<'a>(&self, datapack: &'a Datapack) -> Result<Vec<Namespace<'a>>, String> {
+        if self.datapack.namespaces.is_empty() {
+            return Err(String::from("No namespaces to compile"));
+        }
+
+        self.datapack.namespaces
+            .iter()
+            .map(|name| Namespace::try_new(datapack, name.clone())
+                .map_err(|_| format!("Invalid namespace name \"{}\"", name)))
+            .collect()
+    }
+
+    pub fn get_indentation(&self) -> Result<Indentation, String> {
+        match &self.build.indentation {
+            IndentationConfig::Name(name) if name == "tabs" => Ok(Indentation::Tabs),
+            IndentationConfig::Name(name) if name == "infer" => Ok(Indentation::Infer),
+            IndentationConfig::Width(width) if *width > 0 => Ok(Indentation::Spaces(*width)),
+            IndentationConfig::Name(name) => Err(format!("Expected \"tabs\", \"infer\" or number of spaces as indentation, found \"{}\"", name)),
+            IndentationConfig::Width(width) => Err(format!("Expected \"tabs\", \"infer\" or number of spaces as indentation, found {}", width)),
+        }
+    }
+
+    pub fn get_scope_naming(&self) -> Result<ScopeNaming, String> {
+        ScopeNaming::parse(&self.build.scope_naming)
+            .ok_or_else(|| format!("Expected \"hex\" or \"readable\" as scope naming, found \"{}\"", self.build.scope_naming))
+    }
+
+    pub fn get_constants(&self) -> Result<Vec<(String, String)>, String> {
+        self.constants
+            .iter()
+            .map(|(name, value)| {
+                let name = name.trim_start_matches('$');
+                if !is_constant_name_valid(name) {
+                    return Err(format!("Invalid constant name \"${}\"", name));
+                }
+
+                match value {
+                    toml::Value::String(value) => Ok((name.to_string(), value.clone())),
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => Ok((name.to_string(), value.to_string())),
+                    _ => Err(format!("Constant \"${}\" has to be a string, number or boolean", name)),
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_hasher(&self) -> Result<Hasher, String> {
+        let encoding = HashEncoding::parse(&self.hash.encoding)
+            .ok_or_else(|| format!("Unknown hash encoding \"{}\"", self.hash.encoding))?;
+
+        if self.hash.length == 0 || self.hash.length > encoding.get_max_length() {
+            return Err(format!("Hash length has to be between 1 and {} for {}", encoding.get_max_length(), encoding.get_name()));
+        }
+
+        Ok(Hasher::new()
+            .encoding(encoding)
+            .length(self.hash.length)
+            .salt(self.hash.salt.clone()))
+    }
+
+    pub fn get_lint_levels(&self) -> Result<Vec<(LintRule, Option<Severity>)>, String> {
+        self.lint
+            .iter()
+            .map(|(rule, level)| Ok((
+                LintRule::parse(rule).ok_or_else(|| format!("Unknown lint rule \"{}\"", rule))?,
+                parse_level(level).ok_or_else(|| format!("Unknown lint level \"{}\"", level))?,
+            )))
+            .collect()
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::Display;
+
+use crate::mcfrs::{scope::Scope, source_map::Origin};
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// Problem found in compiled function
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+
+    // Reference name of compiled function
+    pub function: String,
+    // Line of compiled function, or index of function tag value, starting from 1
+    pub line: usize,
+    // Line of .mcf file, if known
+    pub origin: Option<Origin>,
+}
+
+impl Diagnostic {
+    // Line is numbered from 0, as in scope's content
+    pub fn new(severity: Severity, message: String, scope: &Scope, line: usize) -> Self {
+        Self {
+            severity,
+            message,
+            function: scope.get_reference_name(),
+            line: line + 1,
+            origin: scope.origins.get(line).cloned(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.origin {
+            Some(origin) => write!(f, "{}:{} ({}:{}): {}: {}", origin.file, origin.line, self.function, self.line, self.severity, self.message),
+            None => write!(f, "{}:{}: {}: {}", self.function, self.line, self.severity, self.message),
+        }
+    }
+}
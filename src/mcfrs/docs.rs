@@ -0,0 +1,209 @@
+use itertools::Itertools;
+
+use crate::{mcfrs::{compiler::{file_compiler::FileCompiler, indentation_normalizer::IndentationNormalizer, macro_compiler::parse_parameter, pass::PassExt}, scope::Scope}, vanilla::namespace::Namespace};
+
+// "generate function" definition, with "///" comments written right above it
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MacroDoc {
+    pub name: String,
+    // Names with default values
+    pub parameters: Vec<(String, Option<String>)>,
+    // "with" settings, like scope or recursion
+    pub settings: Vec<String>,
+    pub doc: String,
+    pub line: usize,
+}
+
+// .mcf file, with "//!" comments from the top of it
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct FileDoc {
+    pub file: String,
+    pub doc: String,
+    pub macros: Vec<MacroDoc>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Documentation {
+    pub files: Vec<FileDoc>,
+}
+
+fn strip_doc<'b>(line: &'b str, marker: &str) -> Option<&'b str> {
+    let doc = line.trim().strip_prefix(marker)?;
+    Some(doc.strip_prefix(' ').unwrap_or(doc))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl FileDoc {
+    pub fn new(scope: &Scope) -> Self {
+        let mut lines = scope.content
+            .lines()
+            .enumerate()
+            .peekable();
+
+        let doc = lines
+            .peeking_take_while(|(_, line)| strip_doc(line, "//!").is_some())
+            .map(|(_, line)| strip_doc(line, "//!").unwrap())
+            .join("\n");
+
+        let mut macros = Vec::new();
+        let mut macro_doc: Vec<&str> = Vec::new();
+
+        while let Some((i, line)) = lines.next() {
+            if let Some(doc) = strip_doc(line, "///") {
+                macro_doc.push(doc);
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            if words.next() == Some("generate") && words.next() == Some("function") {
+                let name = words.next().unwrap_or_default().to_string();
+                let parameters = words.map(parse_parameter).collect();
+                let settings = lines
+                    .peeking_take_while(|(_, line)| line.trim().split(' ').next() == Some("with"))
+                    .map(|(_, line)| line.trim()["with".len()..].trim().to_string())
+                    .collect();
+
+                macros.push(MacroDoc { name, parameters, settings, doc: macro_doc.join("\n"), line: i + 1 });
+            }
+
+            macro_doc.clear();
+        }
+
+        Self { file: scope.get_source_file(), doc, macros }
+    }
+}
+
+impl Documentation {
+    pub fn new(namespace: &Namespace) -> Self {
+        Self {
+            files: FileCompiler::new(namespace)
+                .pass(IndentationNormalizer::new())
+                .map(|scope| FileDoc::new(&scope))
+                .collect(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Reference\n");
+
+        for file in self.files.iter() {
+            out += &format!("\n## {}\n", file.file);
+            if !file.doc.is_empty() {
+                out += &format!("\n{}\n", file.doc);
+            }
+
+            for definition in file.macros.iter() {
+                out += &format!("\n### `{}`\n\n", definition.name);
+                out += &format!("Defined at {}:{}", file.file, definition.line);
+                if !definition.settings.is_empty() {
+                    out += &format!(", with {}", definition.settings.join(", "));
+                }
+                out += "\n";
+
+                if !definition.doc.is_empty() {
+                    out += &format!("\n{}\n", definition.doc);
+                }
+
+                if !definition.parameters.is_empty() {
+                    out += "\n| Parameter | Default |\n| --- | --- |\n";
+                    for (name, default) in definition.parameters.iter() {
+                        out += &format!("| `{}` | {} |\n", name, match default {
+                            Some(default) => format!("`{}`", default),
+                            None => String::from("required"),
+                        });
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Reference</title></head>\n<body>\n<h1>Reference</h1>\n");
+
+        for file in self.files.iter() {
+            out += &format!("<h2>{}</h2>\n", escape_html(&file.file));
+            if !file.doc.is_empty() {
+                out += &format!("<pre>{}</pre>\n", escape_html(&file.doc));
+            }
+
+            for definition in file.macros.iter() {
+                out += &format!("<h3 id=\"{0}\"><code>{0}</code></h3>\n", escape_html(&definition.name));
+                out += &format!("<p>Defined at {}:{}", escape_html(&file.file), definition.line);
+                if !definition.settings.is_empty() {
+                    out += &format!(", with {}", escape_html(&definition.settings.join(", ")));
+                }
+                out += "</p>\n";
+
+                if !definition.doc.is_empty() {
+                    out += &format!("<pre>{}</pre>\n", escape_html(&definition.doc));
+                }
+
+                if !definition.parameters.is_empty() {
+                    out += "<table>\n<tr><th>Parameter</th><th>Default</th></tr>\n";
+                    for (name, default) in definition.parameters.iter() {
+                        out += &format!("<tr><td><code>{}</code></td><td>{}</td></tr>\n", escape_html(name), match default {
+                            Some(default) => format!("<code>{}</code>", escape_html(default)),
+                            None => String::from("required"),
+                        });
+                    }
+                    out += "</table>\n";
+                }
+            }
+        }
+
+        out + "</body>\n</html>\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vanilla::datapack::Datapack;
+
+    use super::*;
+
+    fn document(content: &str) -> FileDoc {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("signs"), &namespace);
+        scope.content = content.to_string();
+
+        FileDoc::new(&scope)
+    }
+
+    #[test]
+    fn attaches_doc_comments() {
+        let file = document("//! Everything about signs\n\n/// Lost doc\nsay a\n/// Draws particles\n/// around the sign\ngenerate function outline\n\tsay b\ngenerate function plain");
+
+        assert_eq!(file.file, "signs.mcf");
+        assert_eq!(file.doc, "Everything about signs");
+        assert_eq!(file.macros.iter().map(|definition| (definition.name.as_str(), definition.doc.as_str(), definition.line)).collect::<Vec<_>>(), vec![
+            ("outline", "Draws particles\naround the sign", 7),
+            ("plain", "", 9),
+        ]);
+    }
+
+    #[test]
+    fn lists_parameters_and_settings() {
+        let file = document("generate function outline $type $rotation=0\nwith scope\n\tsay $type");
+        let definition = &file.macros[0];
+
+        assert_eq!(definition.parameters, vec![(String::from("$type"), None), (String::from("$rotation"), Some(String::from("0")))]);
+        assert_eq!(definition.settings, vec![String::from("scope")]);
+
+        let markdown = Documentation { files: vec![file] }.to_markdown();
+        assert!(markdown.contains("Defined at signs.mcf:1, with scope\n"));
+        assert!(markdown.contains("| `$type` | required |\n| `$rotation` | `0` |\n"));
+    }
+}
@@ -0,0 +1,327 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{mcfrs::{compiler::{file_compiler::FileCompiler, indentation_normalizer::{Indentation, IndentationNormalizer}, pass::PassExt}, diagnostic::Diagnostic, scope::Scope, util::{is_comment_line, split_comments}}, vanilla::{commands::{next_token, Grammar}, datapack::McVersion, namespace::Namespace}};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+// Words that start execute subcommand, "as" only when it does not follow positioned or rotated
+const SUBCOMMANDS: [&str; 12] = ["align", "anchored", "as", "at", "facing", "in", "positioned", "rotated", "if", "unless", "store", "run"];
+
+fn count_tabs(line: &str) -> usize {
+    line.chars().take_while(|&c| c == '\t').count()
+}
+
+fn has_comment(line: &str) -> bool {
+    let comments = split_comments(line, false);
+    is_comment_line(line) || !comments.comments.is_empty()
+}
+
+// Splits "execute ..." into subcommands, run takes the rest of the line
+fn split_execute<'b>(tokens: &[&'b str]) -> Vec<Vec<&'b str>> {
+    let mut subcommands: Vec<Vec<&str>> = Vec::new();
+
+    for (i, &token) in tokens.iter().enumerate().skip(1) {
+        let is_run = subcommands.last().map(|subcommand| subcommand[0] == "run").unwrap_or(false);
+        let is_start = SUBCOMMANDS.contains(&token)
+            && !(token == "as" && (tokens[i - 1] == "positioned" || tokens[i - 1] == "rotated"));
+
+        match subcommands.last_mut() {
+            Some(subcommand) if is_run || !is_start => subcommand.push(token),
+            _ => subcommands.push(vec![token]),
+        }
+    }
+
+    subcommands
+}
+
+// Rewrites .mcf source in canonical layout
+pub struct Formatter {
+    width: usize,
+    indentation: Indentation,
+    grammar: Grammar,
+}
+
+impl Default for Formatter {
+    fn default() -> Self { Self::new() }
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            indentation: Indentation::default(),
+            grammar: Grammar::new(McVersion::V1_17_4),
+        }
+    }
+
+    // Execute chains longer than width (with tab counted as 4) are broken into "back" lines
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    // Indentation of files read, output is always indented with tabs
+    pub fn indentation(mut self, indentation: Indentation) -> Self {
+        self.indentation = indentation;
+        self
+    }
+
+    // Minecraft version, that tells which arguments take the rest of the line
+    pub fn version(mut self, version: McVersion) -> Self {
+        self.grammar = Grammar::new(version);
+        self
+    }
+
+    // Arguments of line, and the rest of it that is left as it is: <greedy> argument, like message of "say",
+    // trailing comment, or anything after a quote or bracket that is not closed
+    fn split_arguments<'b>(&self, line: &'b str) -> (Vec<&'b str>, &'b str) {
+        // With bytes they start at
+        let mut arguments = Vec::new();
+        let mut start = 0;
+
+        while start <= line.len() && !line[start..].starts_with("//") && !line[start..].starts_with("/*") {
+            match next_token(line, start) {
+                Ok((argument, next)) => {
+                    if !argument.is_empty() {
+                        arguments.push((argument, start));
+                    }
+                    start = next;
+                },
+                Err(_) => break,
+            }
+        }
+        let rest = line.get(start..).unwrap_or_default();
+
+        // Commands are matched with single spaces between arguments
+        let normalized = arguments
+            .iter()
+            .map(|(argument, _)| *argument)
+            .chain(Some(rest).filter(|rest| !rest.is_empty()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let greedy_start = self.grammar.get_greedy_start(&normalized);
+
+        // <greedy> argument takes everything after the space that ends previous argument, even more spaces
+        let mut position = 0;
+        let mut previous_end = 0;
+        for (i, (argument, start)) in arguments.iter().enumerate() {
+            if greedy_start == Some(position) {
+                return (arguments[..i].iter().map(|(argument, _)| *argument).collect(), &line[previous_end + 1..]);
+            }
+            position += argument.len() + 1;
+            previous_end = start + argument.len();
+        }
+
+        (arguments.into_iter().map(|(argument, _)| argument).collect(), rest)
+    }
+
+    // Single spaces between arguments, quotes, brackets, <greedy> arguments and comments are left as they are
+    fn normalize_spaces(&self, line: &str) -> String {
+        let line = line.trim();
+        if is_comment_line(line) {
+            return line.to_string();
+        }
+
+        let (mut arguments, rest) = self.split_arguments(line);
+        if !rest.is_empty() {
+            arguments.push(rest);
+        }
+        arguments.join(" ")
+    }
+
+    // Line with its "back" continuations
+    fn format_chain(&self, chain: &[&str]) -> Vec<String> {
+        let indent = "\t".repeat(count_tabs(chain[0]));
+        let head = chain[0].trim();
+        let continuation = chain[1..]
+            .iter()
+            .map(|line| line.trim()["back".len()..].trim())
+            .collect::<Vec<_>>();
+
+        // Comments inside chain would get lost, so it keeps its layout
+        if head.split(' ').next() != Some("execute") || chain.iter().any(|line| has_comment(line)) {
+            return Some(self.normalize_spaces(head))
+                .into_iter()
+                .chain(continuation.iter().map(|line| format!("back {}", self.normalize_spaces(line))))
+                .map(|line| format!("{}{}", indent, line.trim_end()))
+                .collect();
+        }
+
+        let joined = Some(head)
+            .into_iter()
+            .chain(continuation)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let (mut tokens, rest) = self.split_arguments(&joined);
+        // Message of "run say ..." stays in one piece
+        if !rest.is_empty() {
+            tokens.push(rest);
+        }
+
+        let line = format!("{}{}", indent, tokens.join(" "));
+        if line.len() + indent.len() * 3 <= self.width || tokens.len() == 1 {
+            return vec![line];
+        }
+
+        Some(format!("{}execute", indent))
+            .into_iter()
+            .chain(split_execute(&tokens)
+                .into_iter()
+                .map(|subcommand| format!("{}back {}", indent, subcommand.join(" "))))
+            .collect()
+    }
+
+    pub fn format(&self, content: &str) -> String {
+        let lines = content.lines().collect::<Vec<_>>();
+        let mut formatted: Vec<String> = Vec::new();
+        let mut is_in_comment = false;
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i].trim_end();
+
+            // Content of /* */ comment is written as it is
+            if is_in_comment || line.is_empty() {
+                is_in_comment = is_in_comment && split_comments(line, true).is_in_comment;
+                formatted.push(line.to_string());
+                i += 1;
+                continue;
+            }
+
+            let end = i + 1 + lines[i + 1..]
+                .iter()
+                .take_while(|line| line.trim().split(' ').next() == Some("back"))
+                .count();
+
+            formatted.extend(self.format_chain(&lines[i..end]));
+
+            is_in_comment = lines[i..end]
+                .iter()
+                .fold(false, |is_in_comment, line| split_comments(line, is_in_comment).is_in_comment);
+
+            i = end;
+        }
+
+        Self::format_blank_lines(formatted)
+    }
+
+    // One blank line at most, none at the beginning of block or file,
+    // and one before every top level "generate function" with its doc comments
+    fn format_blank_lines(lines: Vec<String>) -> String {
+        let mut out: Vec<String> = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                let next = lines[i..].iter().find(|line| !line.is_empty());
+                let is_block_start = match (out.last(), next) {
+                    (Some(previous), Some(next)) => count_tabs(next) > count_tabs(previous),
+                    _ => true,
+                };
+
+                if !is_block_start && out.last().map(|line| !line.is_empty()).unwrap_or(false) {
+                    out.push(String::new());
+                }
+                continue;
+            }
+
+            if line.starts_with("generate function") {
+                let docs = out
+                    .iter()
+                    .rev()
+                    .take_while(|line| line.starts_with("///"))
+                    .count();
+                let position = out.len() - docs;
+
+                if position > 0 && !out[position - 1].is_empty() {
+                    out.insert(position, String::new());
+                }
+            }
+
+            out.push(line.clone());
+        }
+
+        while out.last().map(|line| line.is_empty()).unwrap_or(false) {
+            out.pop();
+        }
+
+        match out.is_empty() {
+            true => String::new(),
+            false => out.join("\n") + "\n",
+        }
+    }
+}
+
+// .mcf file of namespace with its formatted content
+pub struct FormattedFile {
+    pub path: std::path::PathBuf,
+    pub content: String,
+    pub is_changed: bool,
+}
+
+impl FormattedFile {
+    pub fn new(scope: &Scope, formatter: &Formatter) -> Self {
+        let path = scope.namespace
+            .get_functions_path()
+            .join(scope.get_source_file());
+        let original = std::fs::read_to_string(&path).unwrap_or_default();
+        let content = formatter.format(&scope.content);
+
+        Self { path, is_changed: content != original, content }
+    }
+
+    // Files with badly indented lines are left out, with their diagnostics
+    pub fn read_all(namespace: &Namespace, formatter: &Formatter) -> (Vec<Self>, Vec<Diagnostic>) {
+        let mut files = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        for scope in FileCompiler::new(namespace) {
+            let errors = Rc::new(RefCell::new(Vec::new()));
+            let found = Rc::clone(&errors);
+            let scope = Some(scope)
+                .into_iter()
+                .pass(IndentationNormalizer::new()
+                    .indentation(formatter.indentation)
+                    .report(Box::new(move |diagnostic| found.borrow_mut().push(diagnostic))))
+                .next()
+                .unwrap();
+
+            let errors = errors.take();
+            match errors.is_empty() {
+                true => files.push(Self::new(&scope, formatter)),
+                false => diagnostics.extend(errors),
+            }
+        }
+
+        (files, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_spaces_between_arguments() {
+        assert_eq!(Formatter::new().format("kill   @s\nscoreboard  players set @s  a 1"), "kill @s\nscoreboard players set @s a 1\n");
+    }
+
+    #[test]
+    fn keeps_messages_and_quotes() {
+        let content = "say  a   b\ntellraw  @a {\"text\":\"a  b\"}\n# comment   spaced\nkill  @s  //  why   not\n";
+
+        assert_eq!(Formatter::new().format(content), "say  a   b\ntellraw @a {\"text\":\"a  b\"}\n# comment   spaced\nkill @s //  why   not\n");
+    }
+
+    #[test]
+    fn keeps_message_of_execute() {
+        assert_eq!(Formatter::new().format("execute  as @a  run say  x   y"), "execute as @a run say  x   y\n");
+    }
+
+    #[test]
+    fn splits_long_execute() {
+        let content = "execute as @a at @s if score @s score matches 5.. positioned ~ ~1 ~ run say  too   long for one line";
+
+        assert_eq!(Formatter::new().format(content), "execute\nback as @a\nback at @s\nback if score @s score matches 5..\nback positioned ~ ~1 ~\nback run say  too   long for one line\n");
+    }
+}
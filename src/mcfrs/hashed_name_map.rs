@@ -0,0 +1,78 @@
+use std::{collections::BTreeMap, fs::File, io::{Read, Write}, path::{Path, PathBuf}};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{mcfrs::{scope::Scope, util::HashedNames}, vanilla::{datapack::Datapack, namespace::Namespace}};
+
+// Function generated with --debug-names, in every namespace that adds hashed objectives
+pub const DISPLAY_NAMES_FUNCTION: &str = "_/display_names";
+
+// Every name made with #[value] in a build, mapped to the value
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Serialize, Deserialize)]
+pub struct HashedNameMap {
+    pub names: BTreeMap<String, String>,
+}
+
+impl HashedNameMap {
+    pub fn new(hashed_names: &HashedNames) -> Self {
+        Self { names: hashed_names.borrow().clone() }
+    }
+
+    // Stored in datapack's directory, next to the source map
+    pub fn get_path(datapack: &Datapack) -> PathBuf {
+        datapack.path
+            .join(&datapack.name)
+            .join("mcfrs.hashes.json")
+    }
+
+    pub fn lookup(&self, hashed_name: &str) -> Option<&String> {
+        self.names.get(hashed_name)
+    }
+
+    // Sets display name of every hashed objective, that scopes of namespace add, to the value it is hashed from
+    pub fn get_display_names_scope<'a>(&self, namespace: &'a Namespace<'a>, scopes: &[Scope<'a>]) -> Option<Scope<'a>> {
+        let objective_regex = Regex::new(r"scoreboard objectives add (\S+)").unwrap();
+
+        let objectives = scopes
+            .iter()
+            .filter(|scope| scope.namespace.name == namespace.name)
+            .flat_map(|scope| objective_regex
+                .captures_iter(&scope.content)
+                .map(|capture| capture[1].to_string())
+                .collect::<Vec<_>>())
+            .filter_map(|objective| Some((self.names.get(&objective)?, objective)))
+            .collect::<BTreeMap<_, _>>();
+
+        if objectives.is_empty() {
+            return None;
+        }
+
+        let mut scope = Scope::new(String::from(DISPLAY_NAMES_FUNCTION), namespace);
+        scope.content = objectives
+            .iter()
+            .map(|(value, objective)| format!("scoreboard objectives modify {} displayname {}",
+                objective,
+                serde_json::json!({ "text": value })))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(scope)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, std::io::Error> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), std::io::Error> {
+        File::create(path)?
+            .write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())
+    }
+}
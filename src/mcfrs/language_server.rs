@@ -0,0 +1,478 @@
+use std::{cell::RefCell, collections::{BTreeSet, HashMap, HashSet}, error::Error, path::PathBuf, rc::Rc, str::FromStr};
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::{notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification as _, PublishDiagnostics}, request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _}, CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri};
+use regex::Regex;
+
+use crate::{mcfrs::{compiler::{file_compiler::FileCompiler, indentation_normalizer::IndentationNormalizer, pass::PassExt}, config::{Config, CONFIG_FILE}, diagnostic::{Diagnostic, Severity}, docs::{FileDoc, MacroDoc}, pipeline::Pipeline, scope::Scope, util::is_comment}, vanilla::namespace::Namespace};
+
+fn get_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Error => DiagnosticSeverity::ERROR,
+    }
+}
+
+fn count_tabs(line: &str) -> usize {
+    line.chars().take_while(|&c| c == '\t').count()
+}
+
+fn get_line_range(line: usize, text: &str) -> Range {
+    Range::new(Position::new(line as u32, 0), Position::new(line as u32, text.chars().count() as u32))
+}
+
+// Whitespace separated word, that character at position is part of
+fn get_word(text: &str, position: Position) -> Option<String> {
+    let line = text.lines().nth(position.line as usize)?;
+    let chars = line.chars().collect::<Vec<_>>();
+    let cursor = (position.character as usize).min(chars.len());
+
+    let start = chars[..cursor].iter().rposition(|c| c.is_whitespace()).map(|i| i + 1).unwrap_or(0);
+    let end = chars[cursor..].iter().position(|c| c.is_whitespace()).map(|i| cursor + i).unwrap_or(chars.len());
+
+    match start < end {
+        true => Some(chars[start..end].iter().collect()),
+        false => None,
+    }
+}
+
+// Serves .mcf files of a namespace to editors, over stdin and stdout
+pub struct LanguageServer<'a> {
+    namespace: &'a Namespace<'a>,
+    config: Config,
+    // Unsaved content of open documents, keyed by file like dir/name.mcf
+    documents: HashMap<String, String>,
+    // Files that have diagnostics shown, so they can be cleared
+    published: HashSet<String>,
+    // Document opened or changed last, problems not coming from any .mcf line are shown there
+    edited: Option<String>,
+    objective_regex: Regex,
+}
+
+impl<'a> LanguageServer<'a> {
+    pub fn new(namespace: &'a Namespace<'a>) -> Self {
+        Self {
+            namespace,
+            config: Config::default(),
+            documents: HashMap::new(),
+            published: HashSet::new(),
+            edited: None,
+            objective_regex: Regex::new(r"scoreboard objectives add (\S+)").unwrap(),
+        }
+    }
+
+    // Project config, that diagnostics are compiled with
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn get_functions_path(&self) -> PathBuf {
+        let path = self.namespace.get_functions_path();
+        path.canonicalize().unwrap_or(path)
+    }
+
+    fn get_file(&self, uri: &Uri) -> Option<String> {
+        let path = uri.as_str().strip_prefix("file://")?;
+        let mut bytes = Vec::new();
+        let mut chars = path.bytes();
+
+        // Percent decoding
+        while let Some(c) = chars.next() {
+            match c {
+                b'%' => {
+                    let hex = [chars.next()?, chars.next()?];
+                    bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+                },
+                c => bytes.push(c),
+            }
+        }
+
+        let path = PathBuf::from(String::from_utf8(bytes).ok()?);
+        let path = path.canonicalize().unwrap_or(path);
+
+        Some(path
+            .strip_prefix(self.get_functions_path()).ok()?
+            .to_string_lossy()
+            .replace('\\', "/"))
+    }
+
+    fn get_uri(&self, file: &str) -> Uri {
+        let path = self.get_functions_path().join(file);
+        let path = path
+            .to_string_lossy()
+            .replace('\\', "/")
+            .bytes()
+            .map(|c| match c.is_ascii_alphanumeric() || b"/-_.~".contains(&c) {
+                true => (c as char).to_string(),
+                false => format!("%{:02X}", c),
+            })
+            .collect::<String>();
+
+        Uri::from_str(&format!("file://{}", path)).unwrap()
+    }
+
+    fn get_text(&self, file: &str) -> String {
+        match self.documents.get(file) {
+            Some(text) => text.clone(),
+            None => std::fs::read_to_string(self.get_functions_path().join(file)).unwrap_or_default(),
+        }
+    }
+
+    // Files of namespace with tabs for indentation, badly indented lines are left as they are
+    fn get_scopes(&self) -> Vec<Scope<'a>> {
+        let indentation = self.config.get_indentation().unwrap_or_default();
+
+        FileCompiler::new(self.namespace)
+            .overlay(self.documents.clone())
+            .pass(IndentationNormalizer::new()
+                .indentation(indentation)
+                .report(Box::new(drop)))
+            .collect()
+    }
+
+    fn get_macros(&self) -> Vec<(String, MacroDoc)> {
+        self.get_scopes()
+            .iter()
+            .flat_map(|scope| {
+                let file = scope.get_source_file();
+                FileDoc::new(scope).macros
+                    .into_iter()
+                    .map(move |definition| (file.clone(), definition))
+            })
+            .collect()
+    }
+
+    fn get_objectives(&self) -> BTreeSet<String> {
+        self.get_scopes()
+            .iter()
+            .flat_map(|scope| self.objective_regex
+                .captures_iter(&scope.content)
+                .map(|captures| captures[1].to_string())
+                .collect::<Vec<_>>())
+            .collect()
+    }
+
+    // Problems found by compiling the namespace, grouped by file
+    fn get_diagnostics(&self) -> HashMap<String, Vec<lsp_types::Diagnostic>> {
+        let found: Rc<RefCell<Vec<Diagnostic>>> = Rc::new(RefCell::new(Vec::new()));
+        let found_pipeline = Rc::clone(&found);
+        // Problems, that do not come from any .mcf line
+        let mut unlocated = Vec::new();
+
+        let pipeline = Pipeline::new()
+            .config(&self.config)
+            .unwrap_or_else(|message| {
+                unlocated.push((Severity::Error, format!("{}: {}", CONFIG_FILE, message)));
+                Pipeline::new()
+            });
+
+        pipeline
+            .overlay(self.documents.clone())
+            .report(Box::new(move |diagnostic| found_pipeline.borrow_mut().push(diagnostic)))
+            .compile(self.namespace)
+            .for_each(drop);
+
+        let mut diagnostics: HashMap<String, Vec<lsp_types::Diagnostic>> = HashMap::new();
+
+        for diagnostic in found.borrow().iter() {
+            let origin = match &diagnostic.origin {
+                Some(origin) => origin,
+                None => {
+                    unlocated.push((diagnostic.severity, diagnostic.to_string()));
+                    continue;
+                },
+            };
+
+            let text = self.get_text(&origin.file);
+            let line = origin.line.saturating_sub(1);
+
+            diagnostics.entry(origin.file.clone()).or_default().push(lsp_types::Diagnostic {
+                range: get_line_range(line, text.lines().nth(line).unwrap_or_default()),
+                severity: Some(get_severity(diagnostic.severity)),
+                source: Some(String::from("mcfrs")),
+                message: diagnostic.message.clone(),
+                ..Default::default()
+            });
+        }
+
+        // Shown at the first line of the document being edited, there is no better place for them
+        if let Some(file) = &self.edited {
+            for (severity, message) in unlocated {
+                diagnostics.entry(file.clone()).or_default().push(lsp_types::Diagnostic {
+                    range: get_line_range(0, self.get_text(file).lines().next().unwrap_or_default()),
+                    severity: Some(get_severity(severity)),
+                    source: Some(String::from("mcfrs")),
+                    message,
+                    ..Default::default()
+                });
+            }
+        }
+
+        diagnostics
+    }
+
+    fn publish_diagnostics(&mut self, connection: &Connection) -> Result<(), Box<dyn Error>> {
+        let diagnostics = self.get_diagnostics();
+        let cleared = self.published
+            .iter()
+            .filter(|file| !diagnostics.contains_key(*file))
+            .map(|file| (file.clone(), Vec::new()))
+            .collect::<Vec<_>>();
+
+        for (file, file_diagnostics) in diagnostics.iter().map(|(file, d)| (file.clone(), d.clone())).chain(cleared) {
+            let params = PublishDiagnosticsParams::new(self.get_uri(&file), file_diagnostics, None);
+            connection.sender.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))?;
+        }
+
+        self.published = diagnostics.into_keys().collect();
+        Ok(())
+    }
+
+    fn find_macro(&self, position: &TextDocumentPositionParams) -> Option<(String, MacroDoc)> {
+        let file = self.get_file(&position.text_document.uri)?;
+        let word = get_word(&self.get_text(&file), position.position)?;
+
+        self.get_macros()
+            .into_iter()
+            .find(|(_, definition)| definition.name == word)
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (file, definition) = self.find_macro(&params.text_document_position_params)?;
+        let text = self.get_text(&file);
+        let line = definition.line - 1;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            self.get_uri(&file),
+            get_line_range(line, text.lines().nth(line).unwrap_or_default()),
+        )))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (file, definition) = self.find_macro(&params.text_document_position_params)?;
+
+        let parameters = definition.parameters
+            .iter()
+            .map(|(name, default)| match default {
+                Some(default) => format!(" {}={}", name, default),
+                None => format!(" {}", name),
+            })
+            .collect::<String>();
+
+        let mut value = format!("```\ngenerate function {}{}\n", definition.name, parameters);
+        for setting in definition.settings.iter() {
+            value += &format!("with {}\n", setting);
+        }
+        value += &format!("```\nDefined at {}:{}", file, definition.line);
+        if !definition.doc.is_empty() {
+            value += &format!("\n\n{}", definition.doc);
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: None,
+        })
+    }
+
+    fn completion(&self, _: CompletionParams) -> Option<CompletionResponse> {
+        let macros = self.get_macros()
+            .into_iter()
+            .map(|(_, definition)| CompletionItem {
+                label: definition.name.clone(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(definition.parameters.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(" ")),
+                documentation: match definition.doc.is_empty() {
+                    true => None,
+                    false => Some(lsp_types::Documentation::String(definition.doc)),
+                },
+                ..Default::default()
+            });
+
+        let objectives = self.get_objectives()
+            .into_iter()
+            .map(|objective| CompletionItem {
+                label: objective,
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(String::from("objective")),
+                ..Default::default()
+            });
+
+        Some(CompletionResponse::Array(macros.chain(objectives).collect()))
+    }
+
+    // Macros and indented blocks, with blocks nested in them
+    #[allow(deprecated)]
+    fn get_symbols(lines: &[&str], start: usize, end: usize) -> Vec<DocumentSymbol> {
+        let is_code = |line: &&str| !line.trim().is_empty() && !is_comment(line) && !line.trim_start().starts_with("//");
+        let mut symbols = Vec::new();
+        let mut i = start;
+
+        while i < end {
+            let line = lines[i];
+            if !is_code(&line) {
+                i += 1;
+                continue;
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let is_macro = words.starts_with(&["generate", "function"]);
+
+            // Macro's body starts after its "with" settings
+            let mut opener = i;
+            if is_macro {
+                while opener + 1 < end && lines[opener + 1].trim_start().starts_with("with ") {
+                    opener += 1;
+                }
+            }
+
+            let indent = count_tabs(line);
+            let block_end = (opener + 1..end)
+                .find(|&j| is_code(&lines[j]) && count_tabs(lines[j]) <= indent)
+                .unwrap_or(end);
+            let last = (opener..block_end)
+                .rev()
+                .find(|&j| is_code(&lines[j]))
+                .unwrap_or(opener);
+
+            if last == opener && !is_macro {
+                i += 1;
+                continue;
+            }
+
+            // Block opened by "back run function" belongs to whole chain
+            let head = (0..=i)
+                .rev()
+                .find(|&j| lines[j].trim_start().split(' ').next() != Some("back"))
+                .unwrap_or(i);
+
+            let (name, kind) = match is_macro {
+                true => (words.get(2).unwrap_or(&"").to_string(), SymbolKind::FUNCTION),
+                false => (lines[head..=opener]
+                    .iter()
+                    .map(|line| line.trim().strip_prefix("back ").unwrap_or(line.trim()))
+                    .collect::<Vec<_>>()
+                    .join(" "), SymbolKind::NAMESPACE),
+            };
+
+            symbols.push(DocumentSymbol {
+                name,
+                detail: match is_macro {
+                    true => Some(words[3..].join(" ")),
+                    false => None,
+                },
+                kind,
+                tags: None,
+                deprecated: None,
+                range: Range::new(Position::new(head as u32, 0), Position::new(last as u32, lines[last].chars().count() as u32)),
+                selection_range: get_line_range(head, lines[head]),
+                children: Some(Self::get_symbols(lines, opener + 1, block_end)),
+            });
+
+            i = block_end;
+        }
+
+        symbols
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let file = self.get_file(&params.text_document.uri)?;
+        let scope = self.get_scopes()
+            .into_iter()
+            .find(|scope| scope.get_source_file() == file)?;
+
+        let lines = scope.content.lines().collect::<Vec<_>>();
+        Some(DocumentSymbolResponse::Nested(Self::get_symbols(&lines, 0, lines.len())))
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => request
+                .extract::<GotoDefinitionParams>(GotoDefinition::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.definition(params))),
+            HoverRequest::METHOD => request
+                .extract::<HoverParams>(HoverRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.hover(params))),
+            Completion::METHOD => request
+                .extract::<CompletionParams>(Completion::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.completion(params))),
+            DocumentSymbolRequest::METHOD => request
+                .extract::<DocumentSymbolParams>(DocumentSymbolRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.document_symbols(params))),
+            method => return Response::new_err(id, lsp_server::ErrorCode::MethodNotFound as i32, format!("Unknown method \"{}\"", method)),
+        };
+
+        match result {
+            Ok(Ok(value)) => Response { id, result: Some(value), error: None },
+            Ok(Err(error)) => Response::new_err(id, lsp_server::ErrorCode::InternalError as i32, error.to_string()),
+            Err(error) => Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, error.to_string()),
+        }
+    }
+
+    fn edit(&mut self, file: String, text: String) {
+        self.edited = Some(file.clone());
+        self.documents.insert(file, text);
+    }
+
+    // Returns true if documents changed
+    fn handle_notification(&mut self, notification: Notification) -> bool {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => notification
+                .extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD).ok()
+                .and_then(|params| Some((self.get_file(&params.text_document.uri)?, params.text_document.text)))
+                .map(|(file, text)| self.edit(file, text))
+                .is_some(),
+            DidChangeTextDocument::METHOD => notification
+                .extract::<lsp_types::DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD).ok()
+                .and_then(|mut params| Some((self.get_file(&params.text_document.uri)?, params.content_changes.pop()?.text)))
+                .map(|(file, text)| self.edit(file, text))
+                .is_some(),
+            DidCloseTextDocument::METHOD => notification
+                .extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD).ok()
+                .and_then(|params| self.get_file(&params.text_document.uri))
+                .map(|file| self.documents.remove(&file))
+                .is_some(),
+            DidSaveTextDocument::METHOD => true,
+            _ => false,
+        }
+    }
+
+    pub fn run(mut self) -> Result<(), Box<dyn Error>> {
+        let (connection, io_threads) = Connection::stdio();
+
+        let capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            definition_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            completion_provider: Some(CompletionOptions::default()),
+            document_symbol_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        };
+        connection.initialize(serde_json::to_value(capabilities)?)?;
+
+        self.publish_diagnostics(&connection)?;
+
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        break;
+                    }
+                    connection.sender.send(Message::Response(self.handle_request(request)))?;
+                },
+                Message::Notification(notification) => {
+                    if self.handle_notification(notification) {
+                        self.publish_diagnostics(&connection)?;
+                    }
+                },
+                Message::Response(_) => {},
+            }
+        }
+
+        drop(connection);
+        io_threads.join()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,443 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::{mcfrs::{call_graph::{get_calls, CallKind}, diagnostic::{Diagnostic, Severity}, scope::Scope, util::is_comment}, vanilla::commands::tokenize};
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+pub enum LintRule {
+    // @e without limit, run for every entity of "as @e"
+    UnlimitedSelector,
+    // Function calling itself with no score or predicate condition anywhere in the cycle
+    UnguardedRecursion,
+    // Score of fake player read, but never set
+    UnwrittenScore,
+    // Objective used, but never added with "scoreboard objectives add"
+    UndeclaredObjective,
+    // execute, that neither runs nor stores anything
+    ExecuteWithoutRun,
+}
+
+impl LintRule {
+    pub const ALL: [LintRule; 5] = [
+        LintRule::UnlimitedSelector,
+        LintRule::UnguardedRecursion,
+        LintRule::UnwrittenScore,
+        LintRule::UndeclaredObjective,
+        LintRule::ExecuteWithoutRun,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            LintRule::UnlimitedSelector => "unlimited-selector",
+            LintRule::UnguardedRecursion => "unguarded-recursion",
+            LintRule::UnwrittenScore => "unwritten-score",
+            LintRule::UndeclaredObjective => "undeclared-objective",
+            LintRule::ExecuteWithoutRun => "execute-without-run",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|rule| rule.get_name() == name)
+    }
+}
+
+// "allow" turns rule off, "warning" and "error" set its severity
+pub fn parse_level(level: &str) -> Option<Option<Severity>> {
+    match level {
+        "allow" => Some(None),
+        "warning" => Some(Some(Severity::Warning)),
+        "error" => Some(Some(Severity::Error)),
+        _ => None,
+    }
+}
+
+// How command touches scoreboard
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+enum ScoreUse<'b> {
+    Read(&'b str, &'b str),
+    Write(&'b str, &'b str),
+    Objective(&'b str),
+    Declare(&'b str),
+}
+
+// Only conditions on scores and predicates count, as they can change between the calls.
+// Others, like "if entity @s", are often always true, so they do not stop recursion.
+fn is_guarded(tokens: &[&str]) -> bool {
+    let end = tokens.iter().position(|&token| token == "run").unwrap_or(tokens.len());
+
+    tokens[..end]
+        .windows(2)
+        .any(|pair| matches!(pair, ["if" | "unless", "score" | "predicate"]))
+}
+
+fn is_unlimited(token: &str) -> bool {
+    token.starts_with("@e") && !token.contains("limit=")
+}
+
+pub struct Linter {
+    levels: HashMap<LintRule, Option<Severity>>,
+    selector_scores_regex: Regex,
+    json_objective_regex: Regex,
+}
+
+impl Default for Linter {
+    fn default() -> Self { Self::new() }
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self {
+            levels: LintRule::ALL
+                .iter()
+                .map(|rule| (*rule, Some(Severity::Warning)))
+                .collect(),
+            selector_scores_regex: Regex::new(r"scores=\{([^}]*)\}").unwrap(),
+            json_objective_regex: Regex::new(r#""objective"\s*:\s*"([^"]+)""#).unwrap(),
+        }
+    }
+
+    // None turns rule off
+    pub fn level(mut self, rule: LintRule, severity: Option<Severity>) -> Self {
+        self.levels.insert(rule, severity);
+        self
+    }
+
+    fn report(&self, diagnostics: &mut Vec<Diagnostic>, rule: LintRule, message: String, scope: &Scope, line: usize) {
+        if let Some(severity) = self.levels[&rule] {
+            diagnostics.push(Diagnostic::new(severity, format!("{} [{}]", message, rule.get_name()), scope, line));
+        }
+    }
+
+    pub fn lint(&self, scopes: &[Scope]) -> Vec<Diagnostic> {
+        let lines = scopes
+            .iter()
+            .map(|scope| scope.content
+                .lines()
+                .map(|line| match is_comment(line) {
+                    true => Vec::new(),
+                    false => tokenize(line.trim()).unwrap_or_default(),
+                })
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut diagnostics = Vec::new();
+
+        self.lint_selectors(scopes, &lines, &mut diagnostics);
+        self.lint_recursion(scopes, &lines, &mut diagnostics);
+        self.lint_scores(scopes, &lines, &mut diagnostics);
+        self.lint_execute(scopes, &lines, &mut diagnostics);
+
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.function.clone(), diagnostic.line));
+        diagnostics
+    }
+
+    fn lint_selectors(&self, scopes: &[Scope], lines: &[Vec<Vec<&str>>], diagnostics: &mut Vec<Diagnostic>) {
+        // Functions run by every entity of "as @e", with the line running them
+        let mut per_entity: HashMap<String, String> = HashMap::new();
+
+        for (scope, scope_lines) in scopes.iter().zip(lines) {
+            for (i, tokens) in scope_lines.iter().enumerate() {
+                let start = match tokens.windows(2).position(|pair| pair[0] == "as" && is_unlimited(pair[1])) {
+                    Some(start) => start + 2,
+                    None => continue,
+                };
+
+                if tokens[start..].iter().any(|token| is_unlimited(token)) {
+                    self.report(diagnostics, LintRule::UnlimitedSelector, String::from("@e without limit inside \"as @e\" runs for every pair of entities"), scope, i);
+                }
+
+                for (kind, reference) in get_calls(&tokens[start..].join(" ")) {
+                    if kind == CallKind::Function {
+                        per_entity.insert(reference.to_string(), format!("{}:{}", scope.get_reference_name(), i + 1));
+                    }
+                }
+            }
+        }
+
+        for (scope, scope_lines) in scopes.iter().zip(lines) {
+            let caller = match per_entity.get(&scope.get_reference_name()) {
+                Some(caller) => caller,
+                None => continue,
+            };
+
+            for (i, tokens) in scope_lines.iter().enumerate() {
+                if tokens.iter().any(|token| is_unlimited(token)) {
+                    self.report(diagnostics, LintRule::UnlimitedSelector, format!("@e without limit in function run by every entity of \"as @e\" at {}", caller), scope, i);
+                }
+            }
+        }
+    }
+
+    fn lint_recursion(&self, scopes: &[Scope], lines: &[Vec<Vec<&str>>], diagnostics: &mut Vec<Diagnostic>) {
+        let mut unguarded: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+
+        for (scope, scope_lines) in scopes.iter().zip(lines) {
+            for (i, tokens) in scope_lines.iter().enumerate() {
+                if is_guarded(tokens) {
+                    continue;
+                }
+
+                for (kind, reference) in get_calls(&tokens.join(" ")) {
+                    if kind == CallKind::Function {
+                        unguarded
+                            .entry(scope.get_reference_name())
+                            .or_default()
+                            .push((reference.to_string(), i));
+                    }
+                }
+            }
+        }
+
+        // Call is unbounded, if callee gets back to caller through unguarded calls only
+        let reaches = |from: &str, to: &str| {
+            let mut visited = HashSet::new();
+            let mut queue = vec![from.to_string()];
+
+            while let Some(reference) = queue.pop() {
+                if reference == to {
+                    return true;
+                }
+                if !visited.insert(reference.clone()) {
+                    continue;
+                }
+
+                queue.extend(unguarded
+                    .get(&reference)
+                    .into_iter()
+                    .flatten()
+                    .map(|(callee, _)| callee.clone()));
+            }
+
+            false
+        };
+
+        for scope in scopes {
+            let reference = scope.get_reference_name();
+
+            for (callee, i) in unguarded.get(&reference).into_iter().flatten() {
+                if reaches(callee, &reference) {
+                    self.report(diagnostics, LintRule::UnguardedRecursion, format!("Calling \"{}\" recurses without any score or predicate condition", callee), scope, *i);
+                }
+            }
+        }
+    }
+
+    fn get_score_uses<'b>(&self, tokens: &[&'b str], line: &'b str) -> Vec<ScoreUse<'b>> {
+        let mut uses = Vec::new();
+        let get = |i: usize| tokens.get(i).copied().unwrap_or_default();
+
+        match tokens.first() {
+            Some(&"scoreboard") => match (get(1), get(2)) {
+                ("objectives", "add") => uses.push(ScoreUse::Declare(get(3))),
+                ("objectives", "remove" | "modify") => uses.push(ScoreUse::Objective(get(3))),
+                ("objectives", "setdisplay") if tokens.len() > 4 => uses.push(ScoreUse::Objective(get(4))),
+                ("players", "set" | "add" | "remove" | "enable") => uses.push(ScoreUse::Write(get(3), get(4))),
+                ("players", "reset") if tokens.len() > 4 => uses.push(ScoreUse::Write(get(3), get(4))),
+                ("players", "get") => uses.push(ScoreUse::Read(get(3), get(4))),
+                ("players", "operation") => {
+                    uses.push(ScoreUse::Write(get(3), get(4)));
+                    uses.push(ScoreUse::Read(get(6), get(7)));
+                    if get(5) != "=" {
+                        uses.push(ScoreUse::Read(get(3), get(4)));
+                    }
+                },
+                _ => {},
+            },
+            Some(&"trigger") => uses.push(ScoreUse::Objective(get(1))),
+            Some(&"execute") => {
+                let end = tokens.iter().position(|&token| token == "run").unwrap_or(tokens.len());
+
+                for i in 1..end {
+                    match (get(i), get(i + 1)) {
+                        ("if" | "unless", "score") => {
+                            uses.push(ScoreUse::Read(get(i + 2), get(i + 3)));
+                            if get(i + 4) != "matches" {
+                                uses.push(ScoreUse::Read(get(i + 5), get(i + 6)));
+                            }
+                        },
+                        ("result" | "success", "score") if get(i - 1) == "store" => uses.push(ScoreUse::Write(get(i + 2), get(i + 3))),
+                        _ => {},
+                    }
+                }
+
+                if end + 1 < tokens.len() {
+                    return uses.into_iter()
+                        .chain(self.get_score_uses(&tokens[end + 1..], line))
+                        .collect();
+                }
+            },
+            _ => {},
+        }
+
+        for captures in self.selector_scores_regex.captures_iter(line) {
+            for score in captures.get(1).unwrap().as_str().split(',') {
+                if let Some((objective, _)) = score.split_once('=') {
+                    uses.push(ScoreUse::Objective(objective.trim()));
+                }
+            }
+        }
+
+        for captures in self.json_objective_regex.captures_iter(line) {
+            uses.push(ScoreUse::Objective(captures.get(1).unwrap().as_str()));
+        }
+
+        uses
+    }
+
+    fn lint_scores(&self, scopes: &[Scope], lines: &[Vec<Vec<&str>>], diagnostics: &mut Vec<Diagnostic>) {
+        let uses = scopes
+            .iter()
+            .zip(lines)
+            .map(|(scope, scope_lines)| scope.content
+                .lines()
+                .zip(scope_lines)
+                .map(|(line, tokens)| self.get_score_uses(tokens, line))
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let declared = uses
+            .iter()
+            .flatten()
+            .flatten()
+            .filter_map(|score_use| match score_use {
+                ScoreUse::Declare(objective) => Some(*objective),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let written = uses
+            .iter()
+            .flatten()
+            .flatten()
+            .filter_map(|score_use| match score_use {
+                ScoreUse::Write(holder, objective) => Some((*holder, *objective)),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        for (scope, scope_uses) in scopes.iter().zip(uses.iter()) {
+            for (i, line_uses) in scope_uses.iter().enumerate() {
+                for score_use in line_uses {
+                    let objective = match score_use {
+                        ScoreUse::Read(holder, objective) => {
+                            // Selectors read scores of entities, that may have been set anywhere
+                            if !holder.starts_with('@') && *holder != "*" && !written.contains(&(*holder, *objective)) {
+                                self.report(diagnostics, LintRule::UnwrittenScore, format!("Score of \"{}\" for \"{}\" is read, but never set", holder, objective), scope, i);
+                            }
+                            objective
+                        },
+                        ScoreUse::Write(_, objective) | ScoreUse::Objective(objective) => objective,
+                        ScoreUse::Declare(_) => continue,
+                    };
+
+                    if !declared.contains(objective) {
+                        self.report(diagnostics, LintRule::UndeclaredObjective, format!("Objective \"{}\" is never added", objective), scope, i);
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_execute(&self, scopes: &[Scope], lines: &[Vec<Vec<&str>>], diagnostics: &mut Vec<Diagnostic>) {
+        for (scope, scope_lines) in scopes.iter().zip(lines) {
+            for (i, tokens) in scope_lines.iter().enumerate() {
+                // Condition with store is the way to save its result
+                if tokens.first() == Some(&"execute") && !tokens.contains(&"run") && !tokens.contains(&"store") {
+                    self.report(diagnostics, LintRule::ExecuteWithoutRun, String::from("execute neither runs nor stores anything"), scope, i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vanilla::{datapack::Datapack, namespace::Namespace};
+
+    use super::*;
+
+    // Messages and lines of diagnostics found in functions of namespace ns, given by name and content
+    fn lint(linter: Linter, functions: &[(&str, &str)]) -> Vec<(String, usize)> {
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let scopes = functions
+            .iter()
+            .map(|(name, content)| {
+                let mut scope = Scope::new(name.to_string(), &namespace);
+                scope.content = content.to_string();
+                scope
+            })
+            .collect::<Vec<_>>();
+
+        linter
+            .lint(&scopes)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, diagnostic.line))
+            .collect()
+    }
+
+    // Rules of diagnostics found in single function
+    fn rules(content: &str) -> Vec<String> {
+        lint(Linter::new(), &[("main", content)])
+            .into_iter()
+            .map(|(message, _)| message[message.rfind('[').unwrap()..].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn finds_unlimited_selectors() {
+        assert_eq!(rules("execute as @e at @s run kill @e[distance=..1]"), vec!["[unlimited-selector]"]);
+        assert_eq!(rules("execute as @e at @s run kill @e[distance=..1,limit=1]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_unguarded_recursion() {
+        assert_eq!(rules("execute if entity @s run function ns:main"), vec!["[unguarded-recursion]"]);
+        assert_eq!(rules("scoreboard objectives add a dummy\nscoreboard players set #i a 0\nexecute if score #i a matches ..9 run function ns:main"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_unwritten_scores() {
+        let content = "scoreboard objectives add a dummy\nexecute if score #i a matches 1 run say a";
+
+        assert_eq!(rules(content), vec!["[unwritten-score]"]);
+        assert_eq!(rules(&format!("scoreboard players set #i a 1\n{}", content)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_undeclared_objectives() {
+        assert_eq!(rules("scoreboard players set @s a 1"), vec!["[undeclared-objective]"]);
+        assert_eq!(rules("scoreboard objectives add a dummy\nscoreboard players set @s a 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_execute_without_run() {
+        assert_eq!(rules("execute as @a if entity @s[tag=a]"), vec!["[execute-without-run]"]);
+        assert_eq!(rules("execute as @a run say a"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sets_severity_of_rules() {
+        let content = "execute as @a\nscoreboard players set @s a 1";
+        let linter = Linter::new()
+            .level(LintRule::UndeclaredObjective, None)
+            .level(LintRule::ExecuteWithoutRun, Some(Severity::Error));
+        let datapack = Datapack::try_new(String::from("no-such-datapack")).unwrap();
+        let namespace = Namespace::try_new(&datapack, String::from("ns")).unwrap();
+        let mut scope = Scope::new(String::from("main"), &namespace);
+        scope.content = content.to_string();
+
+        let found = linter
+            .lint(&[scope])
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.line))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(Severity::Error, 1)]);
+    }
+}
@@ -1,3 +1,14 @@
 pub mod compiler;
 pub mod scope;
 pub mod util;
+pub mod call_graph;
+pub mod source_map;
+pub mod docs;
+pub mod diagnostic;
+pub mod lint;
+pub mod formatter;
+pub mod language_server;
+pub mod pipeline;
+pub mod config;
+pub mod scaffold;
+pub mod hashed_name_map;
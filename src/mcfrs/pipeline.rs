@@ -0,0 +1,271 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}, rc::Rc};
+
+use crate::{mcfrs::{compiler::{back_compiler::BackCompiler, command_validator::CommandValidator, comment_remover::CommentRemover, conditional_compiler::{ConditionalCompiler, DEFAULT_PROFILE}, constants_compiler::ConstantsCompiler, dead_scope_eliminator::DeadScopeEliminator, file_compiler::FileCompiler, indentation_normalizer::{Indentation, IndentationNormalizer}, macro_compiler::MacroCompiler, pass::{Pass, PassExt, Scopes}, reference_validator::ReferenceValidator, scope_deduplicator::ScopeDeduplicator, scope_inliner::ScopeInliner, scopes_compiler::ScopesCompiler, source_header_writer::SourceHeaderWriter, substitutions_compiler::SubstitutionsCompiler}, config::{Config, PassesConfig}, diagnostic::Diagnostic, scope::ScopeNaming, util::{HashedNames, Hasher}}, vanilla::{function_tag::FunctionTag, namespace::Namespace}};
+
+// Shared by every validation pass of the pipeline
+type Report<'a> = Rc<RefCell<Box<dyn FnMut(Diagnostic) + 'a>>>;
+
+// Places in the pipeline, where custom passes can be inserted
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+pub enum InsertionPoint {
+    // Comments are removed, "back" lines joined and constants substituted, "call" is not pasted yet
+    BeforeMacros,
+    // Indented blocks are turned into scopes, "$this" and such are not substituted yet
+    AfterScopes,
+    // Scopes are final, just before they are written as functions
+    PreBurn,
+}
+
+// Every pass, from .mcf files to functions ready to be burned, set up with builder methods
+pub struct Pipeline<'a> {
+    keep_comments: bool,
+    source_headers: bool,
+    externals: Vec<String>,
+    exports: Vec<String>,
+    constants: Vec<(String, String)>,
+    profile: String,
+    hasher: Hasher,
+    hashed_names: HashedNames,
+    overlay: HashMap<String, String>,
+    indentation: Indentation,
+    scope_naming: ScopeNaming,
+    enabled: PassesConfig,
+    report: Report<'a>,
+    inserted: Vec<(InsertionPoint, Box<dyn Pass<'a> + 'a>)>,
+}
+
+impl<'a> Default for Pipeline<'a> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self {
+            keep_comments: false,
+            source_headers: false,
+            externals: Vec::new(),
+            exports: Vec::new(),
+            constants: Vec::new(),
+            profile: String::from(DEFAULT_PROFILE),
+            hasher: Hasher::new(),
+            hashed_names: Rc::new(RefCell::new(BTreeMap::new())),
+            overlay: HashMap::new(),
+            indentation: Indentation::default(),
+            scope_naming: ScopeNaming::default(),
+            enabled: PassesConfig::default(),
+            report: Rc::new(RefCell::new(Box::new(|diagnostic| eprintln!("{}", diagnostic)))),
+            inserted: Vec::new(),
+        }
+    }
+
+    // Keeps "#" comments in compiled functions
+    pub fn keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+        self
+    }
+
+    // Starts every compiled function with "# Compiled from <file>:<line>"
+    pub fn source_headers(mut self, source_headers: bool) -> Self {
+        self.source_headers = source_headers;
+        self
+    }
+
+    // Function provided by other datapack, like ns:name, or ns:* for whole namespace
+    pub fn external(mut self, reference: String) -> Self {
+        self.externals.push(reference);
+        self
+    }
+
+    // Function kept by dead scope elimination, even if nothing in the datapack calls it, like ns:name
+    pub fn export(mut self, reference: String) -> Self {
+        self.exports.push(reference);
+        self
+    }
+
+    // Constant substituted for $name in every file, unless the file defines it itself
+    pub fn define(mut self, name: String, value: String) -> Self {
+        self.constants.push((name, value));
+        self
+    }
+
+    // Build profile, that "#if profile == release" blocks are checked against
+    pub fn profile(mut self, profile: String) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    // How #[value] is hashed
+    pub fn hasher(mut self, hasher: Hasher) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    // Collects hashed names, shared by pipelines of every namespace of a build to find collisions between them
+    pub fn hashed_names(mut self, hashed_names: HashedNames) -> Self {
+        self.hashed_names = hashed_names;
+        self
+    }
+
+    // Content used instead of what is saved on disk, keyed by file like dir/name.mcf
+    pub fn overlay(mut self, overlay: HashMap<String, String>) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    pub fn indentation(mut self, indentation: Indentation) -> Self {
+        self.indentation = indentation;
+        self
+    }
+
+    // How scopes made from indented blocks are named
+    pub fn scope_naming(mut self, scope_naming: ScopeNaming) -> Self {
+        self.scope_naming = scope_naming;
+        self
+    }
+
+    // Optimization and validation passes to run, all of them by default
+    pub fn enabled(mut self, enabled: PassesConfig) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    // Build options of project config
+    pub fn config(self, config: &Config) -> Result<Self, String> {
+        let indentation = config.get_indentation()?;
+        let constants = config.get_constants()?;
+        let hasher = config.get_hasher()?;
+        let scope_naming = config.get_scope_naming()?;
+
+        let pipeline = config.build.externals
+            .iter()
+            .fold(self, |pipeline, external| pipeline.external(external.clone()));
+        let pipeline = config.build.exports
+            .iter()
+            .fold(pipeline, |pipeline, export| pipeline.export(export.clone()));
+
+        Ok(constants
+            .into_iter()
+            .fold(pipeline, |pipeline, (name, value)| pipeline.define(name, value))
+            .profile(config.build.profile.clone())
+            .keep_comments(config.build.keep_comments)
+            .source_headers(config.build.source_headers)
+            .indentation(indentation)
+            .scope_naming(scope_naming)
+            .hasher(hasher)
+            .enabled(config.passes))
+    }
+
+    // Called with every problem found by validation passes, printed to stderr by default
+    pub fn report(mut self, report: Box<dyn FnMut(Diagnostic) + 'a>) -> Self {
+        self.report = Rc::new(RefCell::new(report));
+        self
+    }
+
+    // Runs custom pass at insertion point, after ones inserted there before
+    pub fn insert<P>(mut self, point: InsertionPoint, pass: P) -> Self
+    where P: Pass<'a> + 'a {
+        self.inserted.push((point, Box::new(pass)));
+        self
+    }
+
+    // Takes out passes inserted at the point, in order of insertion
+    fn take_passes(&mut self, point: InsertionPoint) -> Vec<Box<dyn Pass<'a> + 'a>> {
+        let (inserted, rest) = std::mem::take(&mut self.inserted)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(pass_point, _)| *pass_point == point);
+
+        self.inserted = rest;
+        inserted
+            .into_iter()
+            .map(|(_, pass)| pass)
+            .collect()
+    }
+
+    fn run_passes(passes: Vec<Box<dyn Pass<'a> + 'a>>, scopes: Scopes<'a>) -> Scopes<'a> {
+        passes
+            .into_iter()
+            .fold(scopes, |scopes, pass| pass.apply(scopes))
+    }
+
+    // Runs pass only if it is enabled
+    fn optional<P>(enabled: bool, pass: P) -> impl FnOnce(Scopes<'a>) -> Scopes<'a>
+    where P: Pass<'a> + 'a {
+        move |scopes| match enabled {
+            true => Box::new(pass).apply(scopes),
+            false => scopes,
+        }
+    }
+
+    // Passes diagnostics on to the report shared by the whole pipeline
+    fn reporter(&self) -> Box<dyn FnMut(Diagnostic) + 'a> {
+        let report = Rc::clone(&self.report);
+        Box::new(move |diagnostic| (report.borrow_mut())(diagnostic))
+    }
+
+    // Every problem found by passes goes to the report, passes go on without the code it is in
+    pub fn compile(mut self, namespace: &'a Namespace<'a>) -> Scopes<'a> {
+        let before_macros = self.take_passes(InsertionPoint::BeforeMacros);
+        let after_scopes = self.take_passes(InsertionPoint::AfterScopes);
+        let pre_burn = self.take_passes(InsertionPoint::PreBurn);
+
+        let conditionals = self.constants
+            .iter()
+            .fold(ConditionalCompiler::new().profile(self.profile.clone()), |pass, (name, value)| pass.define(name.clone(), value.clone()))
+            .report(self.reporter());
+        let constants = self.constants
+            .iter()
+            .fold(ConstantsCompiler::new(), |pass, (name, value)| pass.define(name.clone(), value.clone()))
+            .report(self.reporter());
+        let eliminator = self.exports
+            .iter()
+            .fold(DeadScopeEliminator::new(), |pass, export| pass.export(export.clone()));
+        // Function tags call scopes from outside as well
+        let inliner = self.exports
+            .iter()
+            .cloned()
+            .chain(FunctionTag::read_all(namespace.datapack)
+                .into_iter()
+                .flat_map(|tag| tag.values)
+                .map(|value| value.id))
+            .fold(ScopeInliner::new(), |pass, export| pass.export(export));
+        let indentation = IndentationNormalizer::new()
+            .indentation(self.indentation)
+            .report(self.reporter());
+        let comments = CommentRemover::new()
+            .keep_comments(self.keep_comments)
+            .report(self.reporter());
+        let macros = MacroCompiler::new().report(self.reporter());
+        let commands = CommandValidator::new().report(self.reporter());
+        let references = self.externals
+            .iter()
+            .fold(ReferenceValidator::new(namespace.datapack), |pass, external| pass.external(external.clone()))
+            .report(self.reporter());
+        let substitutions = SubstitutionsCompiler::new()
+            .report(self.reporter())
+            .hasher(self.hasher)
+            .hashed_names(self.hashed_names);
+
+        FileCompiler::new(namespace)
+            .overlay(self.overlay)
+            .pass(indentation)
+            .pass(conditionals)
+            .pass(comments)
+            .pass(BackCompiler::new())
+            .pass(constants)
+            .pass(|scopes| Self::run_passes(before_macros, scopes))
+            .pass(macros)
+            .pass(ScopesCompiler::new().naming(self.scope_naming))
+            .pass(|scopes| Self::run_passes(after_scopes, scopes))
+            .pass(Self::optional(self.enabled.deduplicate, ScopeDeduplicator::new()))
+            .pass(substitutions)
+            .pass(Self::optional(self.enabled.validate_commands, commands))
+            .pass(Self::optional(self.enabled.inline, inliner))
+            .pass(Self::optional(self.enabled.eliminate_dead_scopes, eliminator))
+            .pass(Self::optional(self.enabled.validate_references, references))
+            .pass(Self::optional(self.source_headers, SourceHeaderWriter::new()))
+            .pass(|scopes| Self::run_passes(pre_burn, scopes))
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use crate::{mcfrs::config::CONFIG_FILE, vanilla::{datapack::{Datapack, McVersion}, namespace::Namespace}};
+
+const LOAD_FUNCTION: &str = "//! Runs once, when the datapack is loaded (function tag minecraft:load)
+
+scoreboard objectives add #[$namespace.ticks] dummy
+say {name} loaded!
+";
+
+const TICK_FUNCTION: &str = "//! Runs every tick (function tag minecraft:tick)
+
+execute as @a run function
+	scoreboard players add @s #[$namespace.ticks] 1
+	execute if score @s #[$namespace.ticks] matches 1200.. run function
+		scoreboard players set @s #[$namespace.ticks] 0
+		call greet @s
+
+/// Says hello to player, once a minute
+generate function greet $player
+	tellraw $player {\"text\":\"Hello from {name}!\"}
+";
+
+// Skeleton of a new project, ready to build: config, pack.mcmeta, load and tick functions
+pub struct Project {
+    // Directory, that mcfrs.toml is written to
+    pub root: PathBuf,
+    pub name: String,
+    pub namespace: String,
+    pub version: McVersion,
+}
+
+impl Project {
+    pub fn try_new(root: PathBuf, name: String, namespace: String) -> Result<Self, String> {
+        if name.is_empty() || !Datapack::is_name_valid(&name) {
+            return Err(format!("Invalid datapack name \"{}\", only a-z, 0-9, - and _ are allowed", name));
+        }
+        if namespace.is_empty() || !Namespace::is_name_valid(&namespace) {
+            return Err(format!("Invalid namespace name \"{}\", only a-z, 0-9, - and _ are allowed", namespace));
+        }
+
+        Ok(Self { root, name, namespace, version: McVersion::V1_17_4 })
+    }
+
+    fn get_config(&self) -> String {
+        format!("[datapack]
+name = \"{name}\"
+description = \"\"
+version = \"{version}\"
+path = \".\"
+namespaces = [\"{namespace}\"]
+
+[build]
+indentation = \"infer\"
+
+[lint]
+# unlimited-selector = \"error\"
+",
+            name = self.name,
+            version = self.version.get_name(),
+            namespace = self.namespace)
+    }
+
+    fn get_pack_mcmeta(&self) -> String {
+        let pack_mcmeta = serde_json::json!({
+            "pack": {
+                "pack_format": self.version.get_pack_format(),
+                "description": self.name,
+            }
+        });
+
+        format!("{}\n", serde_json::to_string_pretty(&pack_mcmeta).unwrap())
+    }
+
+    fn get_tag(&self, function: &str) -> String {
+        let tag = serde_json::json!({
+            "values": [format!("{}:{}", self.namespace, function)]
+        });
+
+        format!("{}\n", serde_json::to_string_pretty(&tag).unwrap())
+    }
+
+    // Paths relative to root, with their content
+    pub fn get_files(&self) -> Vec<(PathBuf, String)> {
+        let datapack = Path::new(&self.name);
+        let functions = datapack.join("data").join(&self.namespace).join("functions");
+        let tags = datapack.join("data").join("minecraft").join("tags").join("functions");
+
+        vec![
+            (PathBuf::from(CONFIG_FILE), self.get_config()),
+            (datapack.join("pack.mcmeta"), self.get_pack_mcmeta()),
+            (tags.join("load.json"), self.get_tag("load")),
+            (tags.join("tick.json"), self.get_tag("tick")),
+            (functions.join("load.mcf"), LOAD_FUNCTION.replace("{name}", &self.name)),
+            (functions.join("tick.mcf"), TICK_FUNCTION.replace("{name}", &self.name)),
+        ]
+    }
+
+    // Nothing is written, if any of the files already exists
+    pub fn write(&self) -> Result<Vec<PathBuf>, String> {
+        let files = self.get_files()
+            .into_iter()
+            .map(|(path, content)| (self.root.join(path), content))
+            .collect::<Vec<_>>();
+
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(format!("{} already exists", path.display()));
+        }
+
+        for (path, content) in files.iter() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|error| format!("{}: {}", parent.display(), error))?;
+            }
+            std::fs::write(path, content).map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+}
@@ -1,4 +1,32 @@
-use crate::vanilla::namespace::Namespace;
+use crate::{mcfrs::{source_map::Origin, util::hash}, vanilla::namespace::Namespace};
+
+// How anonymous scopes (indented blocks) are named
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Default)]
+pub enum ScopeNaming {
+    // _/00/00/00/2A
+    #[default]
+    Hex,
+    // pp/_/3, or pp/_/3_0 for scope nested in pp/_/3
+    Readable,
+}
+
+impl ScopeNaming {
+    pub const ALL: [ScopeNaming; 2] = [ScopeNaming::Hex, ScopeNaming::Readable];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ScopeNaming::Hex => "hex",
+            ScopeNaming::Readable => "readable",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|naming| naming.get_name() == name)
+    }
+}
 
 #[derive(Debug)]
 #[derive(Clone)]
@@ -11,6 +39,8 @@ pub struct Scope<'a> {
     pub parent: Option<Box<Scope<'a>>>,
 
     pub content: String,
+    // Where each line of content comes from, empty if unknown
+    pub origins: Vec<Origin>,
 }
 
 impl<'a> Scope<'a> {
@@ -23,33 +53,58 @@ impl<'a> Scope<'a> {
             (id / ( 1 << 24)) & 255,
             (id / ( 1 << 16)) & 255,
             (id / ( 1 << 8)) & 255,
-            (id / ( 1 << 0)) & 255,
+            id & 255,
         );
 
-        Self { name, namespace, parent: None, content: String::new() }
+        Self { name, namespace, parent: None, content: String::new(), origins: Vec::new() }
     }
 
-    pub fn new_unnamed_scope(id: usize, namespace: &'a Namespace<'a>, body: String) -> Self {
-        let name = format!("_/{:02X}/{:02X}/{:02X}/{:02X}",
-            (id / ( 1 << 24)) & 255,
-            (id / ( 1 << 16)) & 255,
-            (id / ( 1 << 8)) & 255,
-            (id / ( 1 << 0)) & 255,
-        );
+    // Anonymous scope named after its parent and its position among parent's scopes
+    pub fn new_child(parent: &Scope<'a>, position: usize) -> Self {
+        let name = match parent.parent {
+            Some(_) => format!("{}_{}", parent.name, position),
+            None => format!("{}/_/{}", parent.name, position),
+        };
 
-        Self { name, namespace, parent: None, content: body }
+        Self { name, namespace: parent.namespace, parent: None, content: String::new(), origins: Vec::new() }
+            .with_parent(parent)
     }
 
-    pub fn set_reference_id(&mut self, id: usize) {
-        self.name = format!("_/{:02X}/{:02X}/{:02X}/{:02X}",
-            (id / ( 1 << 24)) & 255,
-            (id / ( 1 << 16)) & 255,
-            (id / ( 1 << 8)) & 255,
-            (id / ( 1 << 0)) & 255,
-        );
+    // Parent is kept without its content, only to know where scope comes from
+    pub fn with_parent(mut self, parent: &Scope<'a>) -> Self {
+        self.parent = Some(Box::new(Scope { content: String::new(), origins: Vec::new(), ..parent.clone() }));
+        self
+    }
+
+    // Name depends only on the key, so the same key always gives the same function
+    pub fn new_hashed_scope(prefix: &str, key: &str, namespace: &'a Namespace<'a>, body: String) -> Self {
+        let name = format!("_/{}/{}", prefix, hash(key));
+
+        Self { name, namespace, parent: None, content: body, origins: Vec::new() }
+    }
+
+    // Generated scopes are not written by hand, so nothing outside of the datapack calls them
+    pub fn is_generated(&self) -> bool {
+        self.parent.is_some()
+    }
+
+    // Path of .mcf file, relative to namespace's functions directory
+    pub fn get_source_file(&self) -> String {
+        match &self.parent {
+            Some(parent) => parent.get_source_file(),
+            None => format!("{}.mcf", self.name),
+        }
+    }
+
+    // Place of the first line, like pp.mcf:12
+    pub fn get_source_location(&self) -> String {
+        match self.origins.first() {
+            Some(origin) => format!("{}:{}", origin.file, origin.line),
+            None => self.get_source_file(),
+        }
     }
 
     pub fn new(name: String, namespace: &'a Namespace<'a>) -> Self {
-        Self { name, namespace, parent: None, content: String::new() }
+        Self { name, namespace, parent: None, content: String::new(), origins: Vec::new() }
     }
 }
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, fs::File, io::{Read, Write}, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{mcfrs::scope::Scope, vanilla::datapack::Datapack};
+
+// Place in .mcf file, that line of compiled function comes from
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+#[derive(Serialize, Deserialize)]
+pub struct Origin {
+    // Path of .mcf file, relative to namespace's functions directory
+    pub file: String,
+    // Starting from 1
+    pub line: usize,
+    // Macro calls that pasted the line, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expansion: Vec<String>,
+}
+
+impl Origin {
+    pub fn new(file: String, line: usize) -> Self {
+        Self { file, line, expansion: Vec::new() }
+    }
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        for call in self.expansion.iter().rev() {
+            write!(f, "\n\tpasted by {}", call)?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps origins of lines that are kept, if origins are known
+pub fn retain_origins(origins: Vec<Origin>, is_kept: &[bool]) -> Vec<Origin> {
+    origins
+        .into_iter()
+        .zip(is_kept.iter())
+        .filter(|(_, &is_kept)| is_kept)
+        .map(|(origin, _)| origin)
+        .collect()
+}
+
+// Origins of lines, where some may be unknown, like of a line added by a pass
+// Unknown ones point to the same place as the closest known line above (or below, for leading lines),
+// empty if no line is known
+pub fn fill_origins(origins: Vec<Option<Origin>>) -> Vec<Origin> {
+    let mut last = match origins.iter().flatten().next() {
+        Some(origin) => origin.clone(),
+        None => return Vec::new(),
+    };
+
+    origins
+        .into_iter()
+        .map(|origin| {
+            if let Some(origin) = origin {
+                last = origin;
+            }
+            last.clone()
+        })
+        .collect()
+}
+
+// Origins of every line of every compiled function
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(Serialize, Deserialize)]
+pub struct SourceMap {
+    pub functions: BTreeMap<String, Vec<Origin>>,
+}
+
+impl SourceMap {
+    pub fn new<'a, 'b, I>(scopes: I) -> Self
+    where I: Iterator<Item = &'b Scope<'a>>, 'a: 'b {
+        Self {
+            functions: scopes
+                .map(|scope| (scope.get_reference_name(), scope.origins.clone()))
+                .collect(),
+        }
+    }
+
+    // Source map is stored in datapack's directory, next to pack.mcmeta
+    pub fn get_path(datapack: &Datapack) -> PathBuf {
+        datapack.path
+            .join(&datapack.name)
+            .join("mcfrs.sourcemap.json")
+    }
+
+    // Line is numbered from 1, like in game's error messages
+    pub fn lookup(&self, function: &str, line: usize) -> Option<&Origin> {
+        self.functions
+            .get(function)?
+            .get(line.checked_sub(1)?)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, std::io::Error> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), std::io::Error> {
+        File::create(path)?
+            .write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_unknown_origins_from_neighbours() {
+        let a = Origin::new(String::from("a.mcf"), 2);
+        let b = Origin::new(String::from("a.mcf"), 5);
+
+        let origins = fill_origins(vec![None, Some(a.clone()), None, Some(b.clone()), None]);
+
+        assert_eq!(origins, vec![a.clone(), a.clone(), a, b.clone(), b]);
+    }
+
+    #[test]
+    fn no_known_origin_gives_empty_origins() {
+        assert_eq!(fill_origins(vec![None, None]), Vec::new());
+    }
+}
@@ -1,3 +1,7 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use sha2::Digest;
+
 pub fn get_indent(line: &str) -> usize {
     match line.chars()
         .position(|c| c != '\t') {
@@ -5,3 +9,283 @@ pub fn get_indent(line: &str) -> usize {
             None => 0,
     }
 }
+
+pub fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+// Line of .mcf source, that starts with "#", "//" or "/*"
+pub fn is_comment_line(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with('#') || line.starts_with("//") || line.starts_with("/*")
+}
+
+// Commands, whose message is plain text, so quotes in it do not start strings
+const TEXT_COMMANDS: [&str; 7] = ["say", "me", "msg", "tell", "w", "teammsg", "tm"];
+
+// Line of .mcf source split into code and comments
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub struct LineComments {
+    pub code: String,
+    // Text of every "//" and "/* */" comment, without the markers
+    pub comments: Vec<String>,
+    // Line ends inside of /* */ comment
+    pub is_in_comment: bool,
+    // Comment that line ends inside of, starts on this line
+    pub opens_comment: bool,
+}
+
+// Splits line into code and "//" or "/* */" comments, is_in_comment tells if line starts inside of /* */ comment.
+// Comment starts only after whitespace and outside of quotes, so urls and json text stay intact.
+// Quote starts a string only at the start of an argument or after one of {[(,:=, and never
+// in message of say, me, msg, tell, w, teammsg or tm. Lines beginning with "#" are only code.
+pub fn split_comments(line: &str, mut is_in_comment: bool) -> LineComments {
+    if !is_in_comment && line.trim_start().starts_with('#') {
+        return LineComments { code: line.to_string(), comments: Vec::new(), is_in_comment: false, opens_comment: false };
+    }
+
+    let mut code = String::new();
+    let mut comments: Vec<String> = match is_in_comment {
+        true => vec![String::new()],
+        false => Vec::new(),
+    };
+    let mut opens_comment = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars().peekable();
+    let mut previous = ' ';
+    // Words of code read so far, until message of a text command starts
+    let mut word = String::new();
+    let mut last_word = String::new();
+    let mut is_text = false;
+
+    while let Some(c) = chars.next() {
+        if is_in_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                is_in_comment = false;
+                opens_comment = false;
+                previous = ' ';
+
+                // "a /* b */ c" -> "a c"
+                if code.ends_with(char::is_whitespace) && chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                    chars.next();
+                }
+            } else {
+                comments.last_mut().unwrap().push(c);
+            }
+            continue;
+        }
+
+        match quote {
+            Some(q) if c == q && previous != '\\' => quote = None,
+            Some(_) => {},
+            None if (c == '"' || c == '\'') && !is_text && (previous.is_whitespace() || "{[(,:=".contains(previous)) => quote = Some(c),
+            None if previous.is_whitespace() && c == '/' && chars.peek() == Some(&'/') => {
+                chars.next();
+                comments.push(chars.by_ref().collect());
+                break;
+            },
+            None if previous.is_whitespace() && c == '/' && chars.peek() == Some(&'*') => {
+                chars.next();
+                is_in_comment = true;
+                opens_comment = true;
+                comments.push(String::new());
+                continue;
+            },
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    is_text = is_text || (TEXT_COMMANDS.contains(&word.as_str()) && (last_word.is_empty() || last_word == "run"));
+                    last_word = std::mem::take(&mut word);
+                }
+            },
+            None => word.push(c),
+        }
+
+        code.push(c);
+        previous = c;
+    }
+
+    LineComments { code, comments, is_in_comment, opens_comment }
+}
+
+// Which lines are inside of /* */ comment, that started on a line above
+pub fn get_block_comment_lines(content: &str) -> Vec<bool> {
+    let mut is_in_comment = false;
+
+    content
+        .lines()
+        .map(|line| {
+            let was_in_comment = is_in_comment;
+            is_in_comment = split_comments(line, is_in_comment).is_in_comment;
+
+            was_in_comment
+        })
+        .collect()
+}
+
+// First 16 base32 chars of sha256, lowercased
+pub fn hash(value: &str) -> String {
+    Hasher::new().hash(value)
+}
+
+// Alphabet of hashed values, all of them are valid in objective and tag names,
+// base64url is not valid in storage names or other resource locations, as they have to be lowercase
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Default)]
+pub enum HashEncoding {
+    // a-z, 2-7
+    #[default]
+    Base32,
+    // 0-9, a-v
+    Base32Hex,
+    // 0-9, a-f
+    Hex,
+    // A-Z, a-z, 0-9, - and _, only for objectives and tags
+    Base64Url,
+}
+
+impl HashEncoding {
+    pub const ALL: [HashEncoding; 4] = [
+        HashEncoding::Base32,
+        HashEncoding::Base32Hex,
+        HashEncoding::Hex,
+        HashEncoding::Base64Url,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            HashEncoding::Base32 => "base32",
+            HashEncoding::Base32Hex => "base32hex",
+            HashEncoding::Hex => "hex",
+            HashEncoding::Base64Url => "base64url",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|encoding| encoding.get_name() == name)
+    }
+
+    // Length of whole sha256, without padding
+    pub fn get_max_length(&self) -> usize {
+        match self {
+            HashEncoding::Base32 | HashEncoding::Base32Hex => 52,
+            HashEncoding::Hex => 64,
+            HashEncoding::Base64Url => 43,
+        }
+    }
+
+    fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            HashEncoding::Base32 => data_encoding::BASE32.encode(bytes).to_lowercase(),
+            HashEncoding::Base32Hex => data_encoding::BASE32HEX.encode(bytes).to_lowercase(),
+            HashEncoding::Hex => data_encoding::HEXLOWER.encode(bytes),
+            HashEncoding::Base64Url => data_encoding::BASE64URL_NOPAD.encode(bytes),
+        }
+    }
+}
+
+// Turns #[value] into short name, that is the same for the same value
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub struct Hasher {
+    length: usize,
+    encoding: HashEncoding,
+    // Prepended to every value with its length, so packs using the same readable names do not collide
+    salt: String,
+}
+
+impl Default for Hasher {
+    fn default() -> Self { Self::new() }
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self { length: 16, encoding: HashEncoding::default(), salt: String::new() }
+    }
+
+    // Clamped between 1 and length of whole sha256 in the encoding
+    pub fn length(mut self, length: usize) -> Self {
+        self.length = length.clamp(1, self.encoding.get_max_length());
+        self
+    }
+
+    // Length is clamped again, so set encoding before it
+    pub fn encoding(mut self, encoding: HashEncoding) -> Self {
+        self.encoding = encoding;
+        self.length = self.length.min(encoding.get_max_length());
+        self
+    }
+
+    pub fn salt(mut self, salt: String) -> Self {
+        self.salt = salt;
+        self
+    }
+
+    pub fn hash(&self, value: &str) -> String {
+        let mut hasher = sha2::Sha256::new();
+        // "ab" + "c" and "a" + "bc" are kept apart by length of the salt
+        if !self.salt.is_empty() {
+            hasher.update(format!("{}:{}", self.salt.len(), self.salt));
+        }
+        hasher.update(value);
+
+        let hashed_value = hasher.finalize();
+        self.encoding.encode(&hashed_value)[0..self.length].to_string()
+    }
+}
+
+// Hashed names of a build, mapped to values they come from
+pub type HashedNames = Rc<RefCell<BTreeMap<String, String>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_comments_outside_of_quotes() {
+        let line = split_comments("tellraw @a \"/* no\" /* yes */ // end", false);
+
+        assert_eq!(line.code, "tellraw @a \"/* no\" ");
+        assert_eq!(line.comments, vec![String::from(" yes "), String::from(" end")]);
+        assert!(!line.is_in_comment);
+    }
+
+    #[test]
+    fn finds_lines_in_block_comments() {
+        assert_eq!(get_block_comment_lines("say \"/*\"\nsay a /* b\nc */ say d\n# /* e\nsay f"), vec![false, false, true, false, false]);
+    }
+
+    #[test]
+    fn hashes_with_every_encoding() {
+        assert_eq!(hash("abc"), "xj4bnp4pahh6uqkb");
+        assert_eq!(Hasher::new().encoding(HashEncoding::Base32Hex).hash("abc"), "n9s1dfsf077ukga1");
+        assert_eq!(Hasher::new().encoding(HashEncoding::Hex).length(8).hash("abc"), "ba7816bf");
+        assert_eq!(Hasher::new().encoding(HashEncoding::Base64Url).length(43).hash("abc"), "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+    }
+
+    #[test]
+    fn salt_is_prepended_with_its_length() {
+        assert_eq!(Hasher::new().salt(String::from("pk")).hash("abc"), "mcjscogldaprzn7d");
+    }
+
+    #[test]
+    fn encoding_shortens_length_to_fit() {
+        assert_eq!(Hasher::new().encoding(HashEncoding::Hex).length(64).encoding(HashEncoding::Base64Url).hash("abc").len(), 43);
+    }
+
+    #[test]
+    fn clamps_length() {
+        assert_eq!(Hasher::new().encoding(HashEncoding::Hex).length(65).hash("abc").len(), 64);
+        assert_eq!(Hasher::new().length(0).hash("abc").len(), 1);
+    }
+
+    #[test]
+    fn salt_is_kept_apart_from_value() {
+        assert_ne!(Hasher::new().salt(String::from("a")).hash("bc"), Hasher::new().salt(String::from("ab")).hash("c"));
+    }
+}
@@ -0,0 +1,732 @@
+use std::{cell::Cell, collections::HashMap};
+
+use itertools::Itertools;
+use regex::Regex;
+
+use super::datapack::McVersion;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum ArgumentType {
+    Int,
+    Float,
+    Bool,
+    Word,
+    // Word or quoted string
+    String,
+    Objective,
+    Criteria,
+    Entity,
+    ScoreHolder,
+    IntRange,
+    Vec3,
+    Vec2,
+    BlockPos,
+    Rotation,
+    Resource,
+    // Resource or #tag
+    Function,
+    BlockState,
+    BlockPredicate,
+    ItemStack,
+    ItemPredicate,
+    NbtCompound,
+    NbtTag,
+    NbtPath,
+    Json,
+    Swizzle,
+    Anchor,
+    Operation,
+    Comparison,
+    Slot,
+    Time,
+    // block <pos>, entity <entity> or storage <resource>
+    DataTarget,
+    // block <pos> or entity <entity>
+    ItemTarget,
+    // Rest of the line, as it is
+    Greedy,
+    // Rest of the line, as command
+    Command,
+}
+
+// Names of argument types, as written in usages
+const ARGUMENT_NAMES: &[(&str, ArgumentType)] = &[
+    ("int", ArgumentType::Int),
+    ("float", ArgumentType::Float),
+    ("bool", ArgumentType::Bool),
+    ("word", ArgumentType::Word),
+    ("string", ArgumentType::String),
+    ("objective", ArgumentType::Objective),
+    ("criteria", ArgumentType::Criteria),
+    ("entity", ArgumentType::Entity),
+    ("score_holder", ArgumentType::ScoreHolder),
+    ("int_range", ArgumentType::IntRange),
+    ("vec3", ArgumentType::Vec3),
+    ("vec2", ArgumentType::Vec2),
+    ("block_pos", ArgumentType::BlockPos),
+    ("rotation", ArgumentType::Rotation),
+    ("resource", ArgumentType::Resource),
+    ("function", ArgumentType::Function),
+    ("block_state", ArgumentType::BlockState),
+    ("block_predicate", ArgumentType::BlockPredicate),
+    ("item_stack", ArgumentType::ItemStack),
+    ("item_predicate", ArgumentType::ItemPredicate),
+    ("nbt_compound", ArgumentType::NbtCompound),
+    ("nbt_tag", ArgumentType::NbtTag),
+    ("nbt_path", ArgumentType::NbtPath),
+    ("json", ArgumentType::Json),
+    ("swizzle", ArgumentType::Swizzle),
+    ("anchor", ArgumentType::Anchor),
+    ("operation", ArgumentType::Operation),
+    ("comparison", ArgumentType::Comparison),
+    ("slot", ArgumentType::Slot),
+    ("time", ArgumentType::Time),
+    ("data_target", ArgumentType::DataTarget),
+    ("item_target", ArgumentType::ItemTarget),
+    ("greedy", ArgumentType::Greedy),
+    ("command", ArgumentType::Command),
+];
+
+impl ArgumentType {
+    pub fn parse(name: &str) -> Option<Self> {
+        ARGUMENT_NAMES
+            .iter()
+            .find(|(argument_name, _)| *argument_name == name)
+            .map(|(_, argument)| *argument)
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        ARGUMENT_NAMES
+            .iter()
+            .find(|(_, argument)| argument == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    // How many space separated tokens argument starting with first takes, None if it takes the rest of the line
+    pub fn get_length(&self, first: &str) -> Option<usize> {
+        match self {
+            Self::DataTarget | Self::ItemTarget if first == "block" => Some(4),
+            Self::Vec3 | Self::BlockPos => Some(3),
+            Self::Vec2 | Self::Rotation | Self::DataTarget | Self::ItemTarget => Some(2),
+            Self::Greedy | Self::Command => None,
+            _ => Some(1),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub enum Token {
+    // One of the words
+    Literal(Vec<&'static str>),
+    Argument(ArgumentType),
+}
+
+// Token of usage, optional ones can only be followed by optional ones
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub struct UsageToken {
+    pub token: Token,
+    pub is_optional: bool,
+}
+
+// Argument of command line starting at byte start, with brackets and quotes kept together,
+// and start of the next one. Line ends as if it was followed by a space, at line.len() + 1.
+pub fn next_token(line: &str, start: usize) -> Result<(&str, usize), String> {
+    let mut depth: Vec<char> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in line[start..].char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {},
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '{' => depth.push('}'),
+            '[' => depth.push(']'),
+            '}' | ']' => match depth.pop() {
+                Some(expected) if expected == c => {},
+                _ => return Err(format!("Unexpected \"{}\"", c)),
+            },
+            ' ' if depth.is_empty() => return Ok((&line[start..start + i], start + i + 1)),
+            _ => {},
+        }
+    }
+
+    if quote.is_some() {
+        return Err(String::from("Unclosed quote"));
+    }
+    if let Some(expected) = depth.pop() {
+        return Err(format!("Missing \"{}\"", expected));
+    }
+
+    Ok((&line[start..], line.len() + 1))
+}
+
+// Command line split into arguments, with brackets and quotes kept together
+pub fn tokenize(line: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+
+    while start <= line.len() {
+        let (token, next) = next_token(line, start)?;
+        tokens.push(token);
+        start = next;
+    }
+
+    Ok(tokens)
+}
+
+// Why line did not match usage, and how far it got
+#[derive(Default)]
+struct Mismatch {
+    // Byte of the line, that matching stopped at
+    position: usize,
+    // Tokens, that would let matching go on
+    expected: Vec<String>,
+    // Problem more specific than expected tokens
+    message: Option<String>,
+}
+
+impl Mismatch {
+    fn new(position: usize, message: String) -> Self {
+        Self { position, message: Some(message), ..Default::default() }
+    }
+
+    fn expected(position: usize, expected: String) -> Self {
+        Self { position, expected: vec![expected], ..Default::default() }
+    }
+
+    // Keeps the one that got further, or merges them
+    fn merge(self, other: Mismatch) -> Self {
+        match self.position.cmp(&other.position) {
+            std::cmp::Ordering::Greater => self,
+            std::cmp::Ordering::Less => other,
+            std::cmp::Ordering::Equal => Self {
+                position: self.position,
+                expected: self.expected.into_iter().chain(other.expected).unique().collect(),
+                message: self.message.or(other.message),
+            },
+        }
+    }
+
+    fn into_message(self, line: &str) -> String {
+        let found = match self.position <= line.len() {
+            true => format!("\"{}\"", next_token(line, self.position).map(|(token, _)| token).unwrap_or(&line[self.position..])),
+            false => String::from("end of line"),
+        };
+
+        match (self.message, self.expected.as_slice()) {
+            (Some(message), _) => message,
+            (None, []) => format!("Unexpected {}", found),
+            (None, [expected]) => format!("Expected {}, found {}", expected, found),
+            (None, [expected @ .., last]) => format!("Expected {} or {}, found {}", expected.join(", "), last, found),
+        }
+    }
+}
+
+pub struct Grammar {
+    commands: HashMap<&'static str, Vec<Vec<UsageToken>>>,
+    execute: Vec<Vec<UsageToken>>,
+
+    word_regex: Regex,
+    resource_regex: Regex,
+    block_regex: Regex,
+    item_regex: Regex,
+    coordinate_regex: Regex,
+    int_range_regex: Regex,
+    slot_regex: Regex,
+    time_regex: Regex,
+    criteria_regex: Regex,
+    selector_regex: Regex,
+
+    // Byte that the last matched <greedy> argument starts at
+    greedy_start: Cell<Option<usize>>,
+}
+
+fn parse_usage(usage: &'static str) -> Vec<UsageToken> {
+    usage
+        .split(' ')
+        .map(|token| {
+            let (token, is_optional) = match token.strip_suffix('?') {
+                Some(token) => (token, true),
+                None => (token, false),
+            };
+
+            let token = match token.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+                Some(argument) => Token::Argument(ArgumentType::parse(argument)
+                    .unwrap_or_else(|| panic!("Unknown argument type \"{}\" in \"{}\"", argument, usage))),
+                None => Token::Literal(token.split('|').collect()),
+            };
+
+            UsageToken { token, is_optional }
+        })
+        .collect()
+}
+
+impl Grammar {
+    pub fn new(version: McVersion) -> Self {
+        let (commands, execute) = match version {
+            McVersion::V1_17_4 => (COMMANDS_1_17, EXECUTE_1_17),
+        };
+
+        let mut usages: HashMap<&'static str, Vec<Vec<UsageToken>>> = HashMap::new();
+        for usage in commands.iter() {
+            let name = usage.split(' ').next().unwrap();
+            let tokens: Vec<_> = parse_usage(usage).into_iter().skip(1).collect();
+
+            for name in name.split('|') {
+                usages.entry(name).or_default().push(tokens.clone());
+            }
+        }
+
+        Self {
+            commands: usages,
+            execute: execute.iter().map(|usage| parse_usage(usage)).collect(),
+
+            word_regex: Regex::new(r"^[A-Za-z0-9_\-.+]+$").unwrap(),
+            resource_regex: Regex::new(r"^([a-z0-9_\-.]+:)?[a-z0-9_\-./]+$").unwrap(),
+            block_regex: Regex::new(r"^#?([a-z0-9_\-.]+:)?[a-z0-9_\-./]+(\[.*\])?(\{.*\})?$").unwrap(),
+            item_regex: Regex::new(r"^#?([a-z0-9_\-.]+:)?[a-z0-9_\-./]+(\{.*\})?$").unwrap(),
+            coordinate_regex: Regex::new(r"^([~^]?)(-?(\d+\.?\d*|\.\d+))?$").unwrap(),
+            int_range_regex: Regex::new(r"^(-?\d+)?\.\.(-?\d+)?$").unwrap(),
+            slot_regex: Regex::new(r"^[a-z_]+(\.[a-z0-9_*]+)?$").unwrap(),
+            time_regex: Regex::new(r"^\d+(\.\d+)?[dst]?$").unwrap(),
+            criteria_regex: Regex::new(r"^[a-z0-9_\-.:]+$").unwrap(),
+            selector_regex: Regex::new(r"^@[parse](\[.*\])?$").unwrap(),
+
+            greedy_start: Cell::new(None),
+        }
+    }
+
+    pub fn is_command(&self, name: &str) -> bool {
+        name == "execute" || self.commands.contains_key(name)
+    }
+
+    // Checks if line is a valid command
+    // Line is split into arguments while it is matched, so <greedy> and <command> arguments take the rest of it as it is
+    pub fn validate(&self, line: &str) -> Result<(), String> {
+        self.validate_command(line, 0).map_err(|mismatch| mismatch.into_message(line))
+    }
+
+    // Byte that <greedy> argument of valid command starts at, its content is not made of arguments
+    pub fn get_greedy_start(&self, line: &str) -> Option<usize> {
+        self.greedy_start.set(None);
+        self.validate_command(line, 0).ok()?;
+        self.greedy_start.get()
+    }
+
+    // Command starting at byte start of line, for commands run by execute
+    fn validate_command(&self, line: &str, start: usize) -> Result<(), Mismatch> {
+        let (name, position) = next_token(line, start).map_err(|message| Mismatch::new(start, message))?;
+
+        if name == "execute" {
+            return self.validate_execute(line, position);
+        }
+
+        let usages = self.commands
+            .get(name)
+            .ok_or_else(|| Mismatch::new(usize::MAX, format!("Unknown command \"{}\"", name)))?;
+
+        let mut furthest: Option<Mismatch> = None;
+        for usage in usages {
+            match self.match_usage(usage, line, position, true) {
+                Ok(_) => return Ok(()),
+                Err(mismatch) => furthest = Some(match furthest {
+                    Some(furthest) => furthest.merge(mismatch),
+                    None => mismatch,
+                }),
+            }
+        }
+
+        Err(furthest.unwrap())
+    }
+
+    fn validate_execute(&self, line: &str, start: usize) -> Result<(), Mismatch> {
+        let mut position = start;
+        let mut is_finished = false;
+
+        while position <= line.len() {
+            let mut furthest: Option<Mismatch> = None;
+            let mut end = None;
+
+            for usage in self.execute.iter() {
+                match self.match_usage(usage, line, position, false) {
+                    Ok(new_position) => {
+                        is_finished = matches!(&usage[0].token, Token::Literal(words) if words.contains(&"if") || words.contains(&"run"));
+                        end = Some(new_position);
+                        break;
+                    },
+                    Err(mismatch) => furthest = Some(match furthest {
+                        Some(furthest) => furthest.merge(mismatch),
+                        None => mismatch,
+                    }),
+                }
+            }
+
+            position = match end {
+                Some(end) => end,
+                None => return Err(furthest.unwrap()),
+            };
+        }
+
+        // Execute without run is only useful as a condition
+        match (position, is_finished) {
+            _ if position == start => Err(Mismatch::expected(position, String::from("execute subcommand"))),
+            (_, true) => Ok(()),
+            _ => Err(Mismatch::new(position, String::from("Execute has to end with a condition or run"))),
+        }
+    }
+
+    // Returns position after matched tokens, whole line has to match if is_complete
+    fn match_usage(&self, usage: &[UsageToken], line: &str, position: usize, is_complete: bool) -> Result<usize, Mismatch> {
+        let usage_token = match usage.first() {
+            Some(usage_token) => usage_token,
+            None if !is_complete || position > line.len() => return Ok(position),
+            None => return Err(Mismatch { position, ..Default::default() }),
+        };
+
+        let consumed = self.match_token(&usage_token.token, line, position)
+            .and_then(|position| self.match_usage(&usage[1..], line, position, is_complete));
+
+        match (consumed, usage_token.is_optional) {
+            (Ok(position), _) => Ok(position),
+            (Err(mismatch), true) => self.match_usage(&usage[1..], line, position, is_complete)
+                .map_err(|skipped| mismatch.merge(skipped)),
+            (Err(mismatch), false) => Err(mismatch),
+        }
+    }
+
+    fn match_token(&self, token: &Token, line: &str, position: usize) -> Result<usize, Mismatch> {
+        let expected = match token {
+            Token::Literal(words) => words.join("|"),
+            Token::Argument(argument) => format!("<{}>", argument.get_name()),
+        };
+
+        if position > line.len() {
+            return Err(Mismatch::expected(position, expected));
+        }
+
+        match token {
+            // Rest of the line is taken as it is, without splitting it
+            Token::Argument(ArgumentType::Greedy) => {
+                self.greedy_start.set(Some(position));
+                Ok(line.len() + 1)
+            },
+            Token::Argument(ArgumentType::Command) => self.validate_command(line, position)
+                .map(|_| line.len() + 1),
+            Token::Literal(words) => match next_token(line, position) {
+                Ok((word, next)) if words.contains(&word) => Ok(next),
+                Ok(_) => Err(Mismatch::expected(position, expected)),
+                Err(message) => Err(Mismatch::new(position, message)),
+            },
+            Token::Argument(argument) => {
+                let (first, mut next) = next_token(line, position).map_err(|message| Mismatch::new(position, message))?;
+                let mut tokens = vec![first];
+
+                for _ in 1..argument.get_length(first).unwrap_or(1) {
+                    if next > line.len() {
+                        return Err(Mismatch::expected(next, expected));
+                    }
+
+                    let (token, after) = next_token(line, next).map_err(|message| Mismatch::new(next, message))?;
+                    tokens.push(token);
+                    next = after;
+                }
+
+                self.check_argument(*argument, &tokens)
+                    .map(|_| next)
+                    .map_err(|message| Mismatch::new(position, format!("Invalid {}: {}", expected, message)))
+            },
+        }
+    }
+
+    fn check_coordinates(&self, tokens: &[&str], allow_local: bool, is_block: bool) -> Result<(), String> {
+        let mut kinds = Vec::new();
+
+        for token in tokens {
+            let captures = self.coordinate_regex
+                .captures(token)
+                .filter(|captures| captures.get(1).unwrap().as_str() != "" || captures.get(2).is_some())
+                .ok_or_else(|| format!("\"{}\" is not a coordinate", token))?;
+
+            let kind = captures.get(1).unwrap().as_str();
+            if is_block && kind.is_empty() && token.contains('.') {
+                return Err(format!("\"{}\" is not a whole number", token));
+            }
+            kinds.push(kind);
+        }
+
+        match (kinds.contains(&"^"), kinds.iter().all(|&kind| kind == "^")) {
+            (true, _) if !allow_local => Err(String::from("local coordinates are not allowed here")),
+            (true, false) => Err(String::from("local coordinates can not be mixed with other ones")),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_argument(&self, argument: ArgumentType, tokens: &[&str]) -> Result<(), String> {
+        let token = tokens[0];
+        let check = |is_valid: bool| match is_valid {
+            true => Ok(()),
+            false => Err(format!("\"{}\"", tokens.join(" "))),
+        };
+
+        match argument {
+            ArgumentType::Int => check(token.parse::<i32>().is_ok()),
+            ArgumentType::Float => check(token.parse::<f64>().is_ok()),
+            ArgumentType::Bool => check(token == "true" || token == "false"),
+            ArgumentType::Word => check(self.word_regex.is_match(token)),
+            ArgumentType::String => check(self.word_regex.is_match(token) || token.starts_with('"') || token.starts_with('\'')),
+            ArgumentType::Objective => match self.word_regex.is_match(token) {
+                true if token.chars().count() > 16 => Err(format!("objective \"{}\" is longer than 16 characters", token)),
+                is_valid => check(is_valid),
+            },
+            ArgumentType::Criteria => check(self.criteria_regex.is_match(token)),
+            // Player name or UUID
+            ArgumentType::Entity => match token.starts_with('@') {
+                true => check(self.selector_regex.is_match(token)),
+                false => check(self.word_regex.is_match(token)),
+            },
+            ArgumentType::ScoreHolder => match token.starts_with('@') {
+                true => check(self.selector_regex.is_match(token)),
+                false => check(!token.is_empty()),
+            },
+            ArgumentType::IntRange => check(token.parse::<i32>().is_ok() || (token != ".." && self.int_range_regex.is_match(token))),
+            ArgumentType::Vec3 => self.check_coordinates(tokens, true, false),
+            ArgumentType::Vec2 => self.check_coordinates(tokens, false, false),
+            ArgumentType::BlockPos => self.check_coordinates(tokens, true, true),
+            ArgumentType::Rotation => self.check_coordinates(tokens, false, false),
+            ArgumentType::Resource => check(self.resource_regex.is_match(token)),
+            ArgumentType::Function => check(self.resource_regex.is_match(token.strip_prefix('#').unwrap_or(token))),
+            ArgumentType::BlockState => check(!token.starts_with('#') && self.block_regex.is_match(token)),
+            ArgumentType::BlockPredicate => check(self.block_regex.is_match(token)),
+            ArgumentType::ItemStack => check(!token.starts_with('#') && self.item_regex.is_match(token)),
+            ArgumentType::ItemPredicate => check(self.item_regex.is_match(token)),
+            ArgumentType::NbtCompound => check(token.starts_with('{') && token.ends_with('}')),
+            ArgumentType::NbtTag | ArgumentType::NbtPath => check(!token.is_empty()),
+            ArgumentType::Json => check(token.starts_with('{') || token.starts_with('[') || token.starts_with('"') || self.word_regex.is_match(token)),
+            ArgumentType::Swizzle => check(!token.is_empty() && token.len() <= 3
+                && token.chars().all(|c| "xyz".contains(c))
+                && token.chars().collect::<std::collections::HashSet<_>>().len() == token.len()),
+            ArgumentType::Anchor => check(token == "eyes" || token == "feet"),
+            ArgumentType::Operation => check(["=", "+=", "-=", "*=", "/=", "%=", "<", ">", "><"].contains(&token)),
+            ArgumentType::Comparison => check(["<", "<=", "=", ">=", ">"].contains(&token)),
+            ArgumentType::Slot => check(self.slot_regex.is_match(token)),
+            ArgumentType::Time => check(self.time_regex.is_match(token)),
+            ArgumentType::DataTarget => match token {
+                "block" => self.check_coordinates(&tokens[1..], true, true).map_err(|_| String::from("block target has to be followed by position")),
+                "entity" => self.check_argument(ArgumentType::Entity, &tokens[1..]),
+                "storage" => self.check_argument(ArgumentType::Resource, &tokens[1..]),
+                _ => Err(format!("\"{}\" is not block, entity or storage", token)),
+            },
+            ArgumentType::ItemTarget => match token {
+                "block" => self.check_coordinates(&tokens[1..], true, true).map_err(|_| String::from("block target has to be followed by position")),
+                "entity" => self.check_argument(ArgumentType::Entity, &tokens[1..]),
+                _ => Err(format!("\"{}\" is not block or entity", token)),
+            },
+            ArgumentType::Greedy => Ok(()),
+            ArgumentType::Command => Ok(()),
+        }
+    }
+}
+
+// "a|b" is one of the words, "<type>" is an argument, "?" marks optional token.
+// First word is the command, commands not allowed in functions (op, stop, ...) are left out.
+const COMMANDS_1_17: &[&str] = &[
+    "advancement grant|revoke <entity> everything",
+    "advancement grant|revoke <entity> only <resource> <word>?",
+    "advancement grant|revoke <entity> from|through|until <resource>",
+    "attribute <entity> <resource> get <float>?",
+    "attribute <entity> <resource> base get <float>?",
+    "attribute <entity> <resource> base set <float>",
+    "attribute <entity> <resource> modifier add <word> <string> <float> add|multiply|multiply_base",
+    "attribute <entity> <resource> modifier remove <word>",
+    "attribute <entity> <resource> modifier value get <word> <float>?",
+    "bossbar add <resource> <json>",
+    "bossbar get <resource> max|players|value|visible",
+    "bossbar list",
+    "bossbar remove <resource>",
+    "bossbar set <resource> color|max|name|players|style|value|visible <greedy>?",
+    "clear <entity>? <item_predicate>? <int>?",
+    "clone <block_pos> <block_pos> <block_pos> <greedy>?",
+    "data get <data_target> <nbt_path>? <float>?",
+    "data merge <data_target> <nbt_compound>",
+    "data modify <data_target> <nbt_path> append|merge|prepend|set from <data_target> <nbt_path>?",
+    "data modify <data_target> <nbt_path> append|merge|prepend|set value <nbt_tag>",
+    "data modify <data_target> <nbt_path> insert <int> from <data_target> <nbt_path>?",
+    "data modify <data_target> <nbt_path> insert <int> value <nbt_tag>",
+    "data remove <data_target> <nbt_path>",
+    "datapack <greedy>",
+    "defaultgamemode survival|creative|adventure|spectator",
+    "difficulty peaceful|easy|normal|hard?",
+    "effect clear <entity>? <resource>?",
+    "effect give <entity> <resource> <int>? <int>? <bool>?",
+    "enchant <entity> <resource> <int>?",
+    "experience|xp add|set <entity> <int> levels|points?",
+    "experience|xp query <entity> levels|points",
+    "fill <block_pos> <block_pos> <block_state> destroy|hollow|keep|outline|replace?",
+    "fill <block_pos> <block_pos> <block_state> replace <block_predicate>",
+    "forceload add|remove <vec2> <vec2>?",
+    "forceload remove all",
+    "forceload query <vec2>?",
+    "function <function>",
+    "gamemode survival|creative|adventure|spectator <entity>?",
+    "gamerule <word> <word>?",
+    "give <entity> <item_stack> <int>?",
+    "help <greedy>?",
+    "item modify <item_target> <slot> <resource>",
+    "item replace <item_target> <slot> with <item_stack> <int>?",
+    "item replace <item_target> <slot> from <item_target> <slot> <resource>?",
+    "kill <entity>?",
+    "list uuids?",
+    "locate <word>",
+    "locatebiome <resource>",
+    "loot <greedy>",
+    "me <greedy>",
+    "msg|tell|w <entity> <greedy>",
+    "particle <resource> <greedy>?",
+    "playsound <resource> master|music|record|weather|block|hostile|neutral|player|ambient|voice <entity> <vec3>? <float>? <float>? <float>?",
+    "recipe give|take <entity> <greedy>",
+    "reload",
+    "say <greedy>",
+    "schedule function <function> <time> append|replace?",
+    "schedule clear <function>",
+    "scoreboard objectives list",
+    "scoreboard objectives add <objective> <criteria> <json>?",
+    "scoreboard objectives remove <objective>",
+    "scoreboard objectives setdisplay <word> <objective>?",
+    "scoreboard objectives modify <objective> displayname <json>",
+    "scoreboard objectives modify <objective> rendertype hearts|integer",
+    "scoreboard players list <score_holder>?",
+    "scoreboard players get <score_holder> <objective>",
+    "scoreboard players set|add|remove <score_holder> <objective> <int>",
+    "scoreboard players reset <score_holder> <objective>?",
+    "scoreboard players enable <score_holder> <objective>",
+    "scoreboard players operation <score_holder> <objective> <operation> <score_holder> <objective>",
+    "seed",
+    "setblock <block_pos> <block_state> destroy|keep|replace?",
+    "setworldspawn <block_pos>? <float>?",
+    "spawnpoint <entity>? <block_pos>? <float>?",
+    "spectate <entity>? <entity>?",
+    "spreadplayers <vec2> <float> <float> <bool> <entity>",
+    "spreadplayers <vec2> <float> <float> under <int> <bool> <entity>",
+    "stopsound <entity> <greedy>?",
+    "summon <resource> <vec3>? <nbt_compound>?",
+    "tag <entity> add|remove <word>",
+    "tag <entity> list",
+    "team add <word> <json>?",
+    "team remove|empty <word>",
+    "team join <word> <score_holder>?",
+    "team leave <score_holder>",
+    "team list <word>?",
+    "team modify <word> <word> <greedy>",
+    "teammsg|tm <greedy>",
+    "teleport|tp <entity>",
+    "teleport|tp <vec3>",
+    "teleport|tp <entity> <entity>",
+    "teleport|tp <entity> <vec3> <rotation>?",
+    "teleport|tp <entity> <vec3> facing <vec3>",
+    "teleport|tp <entity> <vec3> facing entity <entity> <anchor>?",
+    "tellraw <entity> <json>",
+    "time add|set <time>",
+    "time set day|night|noon|midnight",
+    "time query daytime|gametime|day",
+    "title <entity> clear|reset",
+    "title <entity> title|subtitle|actionbar <json>",
+    "title <entity> times <int> <int> <int>",
+    "trigger <objective> add|set <int>",
+    "trigger <objective>",
+    "weather clear|rain|thunder <int>?",
+    "worldborder <greedy>",
+];
+
+const EXECUTE_1_17: &[&str] = &[
+    "align <swizzle>",
+    "anchored <anchor>",
+    "as|at <entity>",
+    "facing entity <entity> <anchor>",
+    "facing <vec3>",
+    "in <resource>",
+    "positioned as <entity>",
+    "positioned <vec3>",
+    "rotated as <entity>",
+    "rotated <rotation>",
+    "if|unless block <block_pos> <block_predicate>",
+    "if|unless blocks <block_pos> <block_pos> <block_pos> all|masked",
+    "if|unless data <data_target> <nbt_path>",
+    "if|unless entity <entity>",
+    "if|unless predicate <resource>",
+    "if|unless score <score_holder> <objective> matches <int_range>",
+    "if|unless score <score_holder> <objective> <comparison> <score_holder> <objective>",
+    "store result|success block <block_pos> <nbt_path> byte|short|int|long|float|double <float>",
+    "store result|success bossbar <resource> value|max",
+    "store result|success entity <entity> <nbt_path> byte|short|int|long|float|double <float>",
+    "store result|success score <score_holder> <objective>",
+    "store result|success storage <resource> <nbt_path> byte|short|int|long|float|double <float>",
+    "run <command>",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(line: &str) -> Result<(), String> {
+        Grammar::new(McVersion::V1_17_4).validate(line)
+    }
+
+    #[test]
+    fn tokenizes_brackets_and_quotes_together() {
+        assert_eq!(tokenize("tp @e[tag=a, limit=1] ~ ~1 ~"), Ok(vec!["tp", "@e[tag=a, limit=1]", "~", "~1", "~"]));
+        assert_eq!(tokenize("tellraw @a {\"text\":\"a b\"}"), Ok(vec!["tellraw", "@a", "{\"text\":\"a b\"}"]));
+        assert_eq!(tokenize("say 'a b"), Err(String::from("Unclosed quote")));
+        assert_eq!(tokenize("say a]"), Err(String::from("Unexpected \"]\"")));
+    }
+
+    #[test]
+    fn greedy_arguments_take_rest_of_line() {
+        assert_eq!(validate("say don't panic"), Ok(()));
+        assert_eq!(validate("say [a {b \"c"), Ok(()));
+        assert_eq!(validate("msg @a it's fine"), Ok(()));
+        assert_eq!(validate("execute as @a run say don't panic"), Ok(()));
+        assert_eq!(validate("execute if entity @s run execute at @s run say ]["), Ok(()));
+    }
+
+    #[test]
+    fn quotes_and_brackets_of_json_are_matched() {
+        assert_eq!(validate("tellraw @a {\"text\":\"don't [panic\"}"), Ok(()));
+        assert_eq!(validate("tellraw @a [\"a\", {\"text\":\"b\"}]"), Ok(()));
+        assert_eq!(validate("tellraw @a {\"text\":\"a\""), Err(String::from("Missing \"}\"")));
+        assert_eq!(validate("execute as @a run tellraw @s \"don't"), Err(String::from("Unclosed quote")));
+    }
+
+    #[test]
+    fn reports_where_matching_stopped() {
+        assert_eq!(validate("sey hi"), Err(String::from("Unknown command \"sey\"")));
+        assert_eq!(validate("say"), Err(String::from("Expected <greedy>, found end of line")));
+        assert_eq!(validate("kill @s extra"), Err(String::from("Unexpected \"extra\"")));
+        assert_eq!(validate("tp @s ~ ~"), Err(String::from("Expected <vec3>, found end of line")));
+        assert_eq!(validate("execute as @a"), Err(String::from("Execute has to end with a condition or run")));
+        assert_eq!(validate("execute as @a run kill @q"), Err(String::from("Invalid <entity>: \"@q\"")));
+    }
+
+    #[test]
+    fn accepts_valid_commands() {
+        assert_eq!(validate("scoreboard players operation @s a += #b a"), Ok(()));
+        assert_eq!(validate("data modify storage ns:a path set from block ~ ~ ~ Items"), Ok(()));
+        assert_eq!(validate("execute store result score @s a run data get entity @s Pos[0]"), Ok(()));
+        assert_eq!(validate("execute if score @s a matches 1.. if block ~ ~-1 ~ #minecraft:logs"), Ok(()));
+    }
+}
@@ -8,6 +8,34 @@ pub enum McVersion {
     V1_17_4,
 }
 
+impl McVersion {
+    pub const ALL: [McVersion; 1] = [
+        McVersion::V1_17_4,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            McVersion::V1_17_4 => "1.17.4",
+        }
+    }
+
+    // pack_format of pack.mcmeta
+    pub fn get_pack_format(&self) -> u32 {
+        match self {
+            McVersion::V1_17_4 => 7,
+        }
+    }
+
+    // Takes "1.17.4", or "1.17" for latest supported patch
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(|version| version.get_name() == name || version.get_name().starts_with(&format!("{}.", name)))
+            .max()
+    }
+}
+
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
@@ -33,14 +61,14 @@ impl Datapack {
         for legal_char in "-_".chars() {
             if c == legal_char { return true }
         }
-        return false
+        false
     }
 
     pub fn is_name_valid(name: &str) -> bool {
         for c in name.chars() {
             if !Self::is_char_valid(c) { return false }
         }
-        return true
+        true
 
     }
 
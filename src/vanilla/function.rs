@@ -1,6 +1,8 @@
 use std::{fs::File, io::{Read, Write}, path::{Path, PathBuf}};
 
-use super::namespace::Namespace;
+use walkdir::WalkDir;
+
+use super::{datapack::Datapack, namespace::Namespace};
 
 #[derive(Debug)]
 pub struct Function<'a> {
@@ -46,7 +48,7 @@ impl<'a> Write for Function<'a> {
         match &mut self.file {
             Some(file) => file.flush(),
             None => {
-                Err(std::io::Error::new(std::io::ErrorKind::Other, "No bytes has been written yet"))
+                Err(std::io::Error::other("No bytes has been written yet"))
             }
         }
     }
@@ -61,6 +63,43 @@ impl<'a> Function<'a> {
             .join("functions")
             .join(format!("{}.{}", self.name, "mcfunction"))
     }
+
+    // References of functions in data/<namespace>/functions/**, written in files with extension, like mcfunction or mcf
+    pub fn list_all(datapack: &Datapack, extension: &str) -> Vec<String> {
+        let data_path = datapack.path
+            .join(&datapack.name)
+            .join("data");
+
+        let namespaces = match std::fs::read_dir(&data_path) {
+            Ok(namespaces) => namespaces,
+            Err(_) => return Vec::new(),
+        };
+
+        namespaces
+            .filter_map(|e| e.ok())
+            .flat_map(|namespace| {
+                let namespace_name = namespace.file_name().to_string_lossy().to_string();
+                let functions_path = namespace.path().join("functions");
+
+                WalkDir::new(&functions_path)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().extension().map(|e| e == extension).unwrap_or(false))
+                    .filter_map(|e| {
+                        let name = e.path()
+                            .strip_prefix(&functions_path).ok()?
+                            .with_extension("")
+                            .to_string_lossy()
+                            .replace('\\', "/");
+
+                        Some(format!("{}:{}", namespace_name, name))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     pub fn is_char_valid(c: char) -> bool {
         for legal_char in 'a'..='z' {
             if c == legal_char { return true }
@@ -71,14 +110,14 @@ impl<'a> Function<'a> {
         for legal_char in "-_/".chars() {
             if c == legal_char { return true }
         }
-        return false
+        false
     }
 
     pub fn is_name_valid(name: &str) -> bool {
         for c in name.chars() {
             if !Self::is_char_valid(c) { return false }
         }
-        return true
+        true
 
     }
 
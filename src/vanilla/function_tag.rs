@@ -0,0 +1,147 @@
+use std::{fs::File, io::Read, path::Path};
+
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use super::datapack::Datapack;
+
+// Entry of tag's "values", either "ns:name" or {"id": "ns:name", "required": false}
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+#[derive(Deserialize)]
+#[serde(from = "TagValueJson")]
+pub struct TagValue {
+    // Function, or tag starting with #
+    pub id: String,
+    // Game skips optional values that do not exist, instead of failing to load the tag
+    pub required: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagValueJson {
+    Id(String),
+    Entry {
+        id: String,
+        #[serde(default = "is_required_by_default")]
+        required: bool,
+    },
+}
+
+fn is_required_by_default() -> bool { true }
+
+impl From<TagValueJson> for TagValue {
+    fn from(value: TagValueJson) -> Self {
+        match value {
+            TagValueJson::Id(id) => Self { id, required: true },
+            TagValueJson::Entry { id, required } => Self { id, required },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TagJson {
+    #[serde(default)]
+    replace: bool,
+    #[serde(default)]
+    values: Vec<TagValue>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Hash)]
+pub struct FunctionTag {
+    // Reference name, like minecraft:load
+    pub name: String,
+    // Functions and tags (starting with #) that tag runs
+    pub values: Vec<TagValue>,
+    // Values of the same tag in datapacks loaded before are dropped
+    pub replace: bool,
+}
+
+impl FunctionTag {
+    // Reads data/<namespace>/tags/functions/**/*.json files of datapack, files that are not valid tags are left out
+    pub fn read_all(datapack: &Datapack) -> Vec<Self> {
+        let data_path = datapack.path
+            .join(&datapack.name)
+            .join("data");
+
+        let namespaces = match std::fs::read_dir(&data_path) {
+            Ok(namespaces) => namespaces,
+            Err(_) => return Vec::new(),
+        };
+
+        namespaces
+            .filter_map(|e| e.ok())
+            .flat_map(|namespace| {
+                let namespace_name = namespace.file_name().to_string_lossy().to_string();
+                let tags_path = namespace.path().join("tags").join("functions");
+
+                WalkDir::new(&tags_path)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().extension().map(|e| e == "json").unwrap_or(false))
+                    .filter_map(|e| Self::read(&namespace_name, &tags_path, e.path()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn read(namespace: &str, tags_path: &Path, path: &Path) -> Option<Self> {
+        let mut content = String::new();
+        File::open(path).ok()?.read_to_string(&mut content).ok()?;
+
+        let name = path
+            .strip_prefix(tags_path).ok()?
+            .with_extension("")
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        Self::parse(format!("{}:{}", namespace, name), &content)
+    }
+
+    // Tag named name, from content of its .json file
+    pub fn parse(name: String, content: &str) -> Option<Self> {
+        let tag: TagJson = serde_json::from_str(content).ok()?;
+
+        Some(Self { name, values: tag.values, replace: tag.replace })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_forms_of_values() {
+        let content = r##"{"replace": true, "values": ["ns:a", {"id": "#ns:b", "required": false}, {"id": "ns:c"}]}"##;
+
+        assert_eq!(FunctionTag::parse(String::from("minecraft:load"), content), Some(FunctionTag {
+            name: String::from("minecraft:load"),
+            values: vec![
+                TagValue { id: String::from("ns:a"), required: true },
+                TagValue { id: String::from("#ns:b"), required: false },
+                TagValue { id: String::from("ns:c"), required: true },
+            ],
+            replace: true,
+        }));
+    }
+
+    #[test]
+    fn reads_escaped_strings() {
+        let tag = FunctionTag::parse(String::from("ns:t"), r#"{"values": ["ns:\u0061"]}"#).unwrap();
+
+        assert_eq!(tag.values[0].id, "ns:a");
+        assert!(!tag.replace);
+    }
+
+    #[test]
+    fn rejects_invalid_tag() {
+        assert_eq!(FunctionTag::parse(String::from("ns:t"), r#"{"values": [1]}"#), None);
+        assert_eq!(FunctionTag::parse(String::from("ns:t"), "not json"), None);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod datapack;
 pub mod namespace;
 pub mod function;
+pub mod function_tag;
+pub mod commands;
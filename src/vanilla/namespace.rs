@@ -18,7 +18,6 @@ impl<'a> Namespace<'a> {
             .join("data")
             .join(&self.name)
             .join("functions")
-            .into()
     }
 
     pub fn is_char_valid(c: char) -> bool {
@@ -31,14 +30,14 @@ impl<'a> Namespace<'a> {
         for legal_char in "-_".chars() {
             if c == legal_char { return true }
         }
-        return false
+        false
     }
 
     pub fn is_name_valid(name: &str) -> bool {
         for c in name.chars() {
             if !Self::is_char_valid(c) { return false }
         }
-        return true
+        true
 
     }
 